@@ -6,10 +6,14 @@
 #
 # Compressed formats benchmarked:
 # - .tar.gz
+# - .tar.xz and .tar.zst, with one thread and with one thread per CPU
 # - .zip
 #
 # Uncompressed formats benchmarked:
 # - .tar
+#
+# Zip extraction is also benchmarked with `rust`, which has many files, with one thread
+# and with one thread per CPU.
 
 set -e
 
@@ -69,6 +73,30 @@ function tar_gz_decompression() {
     $DESCOMPRESSION_CLEANUP
 }
 
+function tar_xz_compression() {
+    cleanup="rm output.tar.xz"
+
+    call_hyperfine \
+        'ouch compress compiler output.tar.xz --threads 1' \
+        'ouch compress compiler output.tar.xz' \
+        'tar -cvJf output.tar.xz compiler' \
+        --prepare "$cleanup || true"
+
+    $cleanup
+}
+
+function tar_zst_compression() {
+    cleanup="rm output.tar.zst"
+
+    call_hyperfine \
+        'ouch compress compiler output.tar.zst --threads 1' \
+        'ouch compress compiler output.tar.zst' \
+        'tar -cv --zstd -f output.tar.zst compiler' \
+        --prepare "$cleanup || true"
+
+    $cleanup
+}
+
 function zip_compression() {
     cleanup="rm output.zip"
 
@@ -92,13 +120,29 @@ function zip_decompression() {
     $DESCOMPRESSION_CLEANUP
 }
 
+function zip_many_files_decompression() {
+    echo "Creating zip archive of many files to benchmark decompression..."
+    ouch compress rust input.zip --yes &> /dev/null
+
+    call_hyperfine \
+        'ouch decompress input.zip --dir output --threads 1' \
+        'ouch decompress input.zip --dir output' \
+        'unzip input.zip -d output' \
+        --prepare "$DESCOMPRESSION_CLEANUP || true"
+
+    $DESCOMPRESSION_CLEANUP
+}
+
 function run_benches() {
     tar_compression
     tar_decompression
     tar_gz_compression
     tar_gz_decompression
+    tar_xz_compression
+    tar_zst_compression
     zip_compression
     zip_decompression
+    zip_many_files_decompression
 }
 
 function concatenate_results() {
@@ -106,8 +150,11 @@ function concatenate_results() {
         tar_decompression.md <(echo) \
         tar_gz_compression.md <(echo) \
         tar_gz_decompression.md <(echo) \
+        tar_xz_compression.md <(echo) \
+        tar_zst_compression.md <(echo) \
         zip_compression.md <(echo) \
-        zip_decompression.md > results.md
+        zip_decompression.md <(echo) \
+        zip_many_files_decompression.md > results.md
 }
 
 run_benches
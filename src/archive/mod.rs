@@ -1,4 +1,37 @@
 //! Archive compression algorithms
 
+use std::io::Read;
+
+use crate::error::FinalError;
+
+#[cfg(feature = "unrar")]
+pub mod rar;
+#[cfg(not(feature = "unrar"))]
+#[path = "rar_stub.rs"]
+pub mod rar;
+mod safe_extract;
+pub mod sevenz;
 pub mod tar;
 pub mod zip;
+
+/// Longest symlink target read from the contents of an archive entry, `PATH_MAX` on Linux
+const MAX_LINK_TARGET_LEN: u64 = 4096;
+
+/// Read the target of the symlink `entry_name`, stored as the contents of its entry, like zip and 7z do
+///
+/// The size declared by the archive can't be trusted, so reading stops after `MAX_LINK_TARGET_LEN` bytes.
+fn read_link_target(entry: impl Read, entry_name: &str) -> crate::Result<String> {
+    let mut target = String::new();
+    entry.take(MAX_LINK_TARGET_LEN + 1).read_to_string(&mut target)?;
+
+    if target.len() as u64 > MAX_LINK_TARGET_LEN {
+        return Err(
+            FinalError::with_title(format!("Could not read the symlink '{entry_name}'"))
+                .detail(format!("Its target is longer than {MAX_LINK_TARGET_LEN} bytes"))
+                .hint("The archive may be corrupted or crafted")
+                .into(),
+        );
+    }
+
+    Ok(target)
+}
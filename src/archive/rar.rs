@@ -0,0 +1,109 @@
+//! Contains RAR-specific unpacking functions
+
+use std::{io::Write, path::Path};
+
+use ubyte::ToByteUnit;
+use unrar::Archive;
+
+use crate::{
+    info,
+    list::{EntryKind, FileInArchive},
+    utils::{strip_cur_dir, EntryFilter, EntryLookup},
+};
+
+/// Unpacks the archive given by `archive_path` into the folder given by `output_folder`,
+/// skipping entries not selected by `entry_filter`.
+/// Assumes that output_folder is empty
+pub fn unpack_archive(
+    archive_path: &Path,
+    output_folder: &Path,
+    entry_filter: &EntryFilter,
+    quiet: bool,
+) -> crate::Result<usize> {
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut archive = Archive::new(archive_path).open_for_processing()?;
+    let mut unpacked_files = 0;
+
+    while let Some(header) = archive.read_header()? {
+        let entry = header.entry();
+
+        if !entry_filter.is_selected(&entry.filename) {
+            archive = header.skip()?;
+            continue;
+        }
+
+        // This is printed for every file in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        if !quiet {
+            info!(
+                inaccessible,
+                "{:?} extracted. ({})",
+                strip_cur_dir(&output_folder.join(&entry.filename)),
+                entry.unpacked_size.bytes()
+            );
+        }
+
+        archive = header.extract_with_base(output_folder)?;
+        unpacked_files += 1;
+    }
+
+    Ok(unpacked_files)
+}
+
+/// Reads every entry of the archive given by `archive_path` without writing anything to disk,
+/// failing if the archive is corrupted
+pub fn test_archive(archive_path: &Path) -> crate::Result<()> {
+    let mut archive = Archive::new(archive_path).open_for_processing()?;
+
+    while let Some(header) = archive.read_header()? {
+        archive = header.test()?;
+    }
+
+    Ok(())
+}
+
+/// Writes the content of the file entry at `entry_path` to `writer`
+pub fn cat_entry(archive_path: &Path, entry_path: &Path, writer: &mut dyn Write) -> crate::Result<()> {
+    let mut archive = Archive::new(archive_path).open_for_processing()?;
+    let mut lookup = EntryLookup::new(entry_path);
+
+    while let Some(header) = archive.read_header()? {
+        let entry = header.entry();
+
+        if !entry.is_directory() && lookup.matches(&entry.filename) {
+            let (content, _) = header.read()?;
+            writer.write_all(&content)?;
+            return Ok(());
+        }
+
+        archive = header.skip()?;
+    }
+
+    Err(lookup.not_found_error())
+}
+
+/// List contents of `archive_path`, returning a vector of archive entries
+pub fn list_archive(archive_path: &Path) -> crate::Result<impl Iterator<Item = crate::Result<FileInArchive>>> {
+    let archive = Archive::new(archive_path).open_for_listing()?;
+
+    let files = archive.map(|header| {
+        let header = header?;
+        let kind = if header.is_directory() {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        };
+
+        Ok(FileInArchive {
+            path: header.filename,
+            kind,
+            size: Some(header.unpacked_size),
+            ..Default::default()
+        })
+    });
+
+    Ok(files)
+}
@@ -0,0 +1,36 @@
+//! Stand-in for the `rar` module when ouch is built without the `unrar` feature
+
+use std::{io::Write, path::Path};
+
+use crate::{error::FinalError, list::FileInArchive, utils::EntryFilter};
+
+fn no_support() -> FinalError {
+    FinalError::with_title("Processing .rar archives is not supported")
+        .detail("This build of ouch was compiled without the 'unrar' feature")
+        .hint("Rebuild ouch with `cargo install ouch --features unrar` to enable RAR support")
+}
+
+/// Always fails, see [`no_support`]
+pub fn unpack_archive(
+    _archive_path: &Path,
+    _output_folder: &Path,
+    _entry_filter: &EntryFilter,
+    _quiet: bool,
+) -> crate::Result<usize> {
+    Err(no_support().into())
+}
+
+/// Always fails, see [`no_support`]
+pub fn test_archive(_archive_path: &Path) -> crate::Result<()> {
+    Err(no_support().into())
+}
+
+/// Always fails, see [`no_support`]
+pub fn cat_entry(_archive_path: &Path, _entry_path: &Path, _writer: &mut dyn Write) -> crate::Result<()> {
+    Err(no_support().into())
+}
+
+/// Always fails, see [`no_support`]
+pub fn list_archive(_archive_path: &Path) -> crate::Result<std::iter::Empty<crate::Result<FileInArchive>>> {
+    Err(no_support().into())
+}
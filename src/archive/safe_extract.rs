@@ -0,0 +1,219 @@
+//! Keeps archive entries from being written outside of the directory they are extracted to
+
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+};
+
+use fs_err as fs;
+
+use crate::{utils::EscapedPathDisplay, warning};
+
+/// Symlinks followed while resolving a path before giving up, like `ELOOP` on Linux
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Validates the paths of archive entries against the directory they are extracted to
+///
+/// An archive can create a symlink `evil -> /etc` and then write `evil/passwd` through it, so symlinks
+/// are only created by `finish`, after every other entry was written, and each of them is checked
+/// against the final state of the directory.
+pub struct SafeExtractor {
+    /// The directory entries are extracted to, as given by the caller
+    output_folder: PathBuf,
+    /// Canonical `output_folder`, paths are resolved relative to it
+    root: PathBuf,
+    /// Symlinks to be created by `finish`, paths relative to `root` and their targets
+    symlinks: Vec<(PathBuf, PathBuf)>,
+}
+
+impl SafeExtractor {
+    pub fn new(output_folder: &Path) -> crate::Result<Self> {
+        Ok(Self {
+            output_folder: output_folder.to_path_buf(),
+            root: fs::canonicalize(output_folder)?,
+            symlinks: vec![],
+        })
+    }
+
+    /// Path where the entry at `entry_path` should be written, `None` if it would end up outside of
+    /// the output folder, in which case the entry is reported as skipped
+    pub fn destination(&self, entry_path: &Path) -> Option<PathBuf> {
+        let Some(relative_path) = normalize(entry_path) else {
+            self.skip(entry_path, "its path leaves the output directory");
+            return None;
+        };
+
+        // Parents that already exist may be symlinks, none of them can lead outside
+        let parent = relative_path.parent().unwrap_or(Path::new(""));
+        if !resolves_inside(&self.root, parent) {
+            self.skip(
+                entry_path,
+                "one of its parent directories leads outside of the output directory",
+            );
+            return None;
+        }
+
+        Some(self.output_folder.join(relative_path))
+    }
+
+    /// Schedule the creation of a symlink at `entry_path` pointing to `target`, see `finish`
+    ///
+    /// Returns whether the symlink was accepted.
+    pub fn add_symlink(&mut self, entry_path: &Path, target: &Path) -> bool {
+        let Some(relative_path) = normalize(entry_path) else {
+            self.skip(entry_path, "its path leaves the output directory");
+            return false;
+        };
+
+        let parent = relative_path.parent().unwrap_or(Path::new(""));
+        if target.has_root() || normalize(&parent.join(target)).is_none() {
+            self.skip(
+                entry_path,
+                &format!(
+                    "it links to '{}', outside of the output directory",
+                    EscapedPathDisplay::new(target)
+                ),
+            );
+            return false;
+        }
+
+        self.symlinks.push((relative_path, target.to_path_buf()));
+        true
+    }
+
+    /// Whether a hardlink at `entry_path` can be created to `target`, a path relative to the output
+    /// folder like the paths of the entries, otherwise the entry is reported as skipped
+    pub fn accepts_hardlink(&self, entry_path: &Path, target: &Path) -> bool {
+        let inside = normalize(target).is_some_and(|target| resolves_inside(&self.root, &target));
+        if !inside {
+            self.skip(
+                entry_path,
+                &format!(
+                    "it links to '{}', outside of the output directory",
+                    EscapedPathDisplay::new(target)
+                ),
+            );
+        }
+        inside
+    }
+
+    /// Create the scheduled symlinks, returning how many were kept
+    ///
+    /// Symlinks can point through each other, so they are checked again once all of them exist,
+    /// those that lead outside of the output folder are removed.
+    pub fn finish(self) -> crate::Result<usize> {
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut created: Vec<(PathBuf, PathBuf, PathBuf)> = vec![];
+
+        for (relative_path, target) in self.symlinks {
+            let path = self.output_folder.join(&relative_path);
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::symlink_metadata(&path).is_ok() {
+                warning!(
+                    "Skipping symlink '{}', a file with the same path was already extracted.",
+                    EscapedPathDisplay::new(&relative_path)
+                );
+                continue;
+            }
+
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink(&target, &path)?;
+                created.push((relative_path, path, target));
+            }
+            #[cfg(not(unix))]
+            warning!(
+                "Skipping symlink '{}' to '{}', symlinks are only extracted on unix systems.",
+                EscapedPathDisplay::new(&relative_path),
+                EscapedPathDisplay::new(&target)
+            );
+        }
+
+        let mut kept = 0;
+        for (relative_path, path, target) in created {
+            if resolves_inside(&self.root, &relative_path) {
+                kept += 1;
+            } else {
+                fs::remove_file(&path)?;
+                warning!(
+                    "Removed symlink '{}', '{}' leads outside of the output directory through other symlinks.",
+                    EscapedPathDisplay::new(&relative_path),
+                    EscapedPathDisplay::new(&target)
+                );
+            }
+        }
+
+        Ok(kept)
+    }
+
+    fn skip(&self, entry_path: &Path, reason: &str) {
+        warning!("Skipping '{}', {}.", EscapedPathDisplay::new(entry_path), reason);
+    }
+}
+
+/// Remove `.` components, failing for absolute paths and `..` components that go above the start
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normalized)
+}
+
+/// Resolve `path`, relative to `root`, the way the OS would, following the symlinks that already
+/// exist, and check that it never goes above `root`
+///
+/// Components that don't exist yet are taken as regular directories.
+fn resolves_inside(root: &Path, path: &Path) -> bool {
+    let mut resolved: Vec<OsString> = vec![];
+    let mut pending: VecDeque<PathBuf> = path.components().map(|c| PathBuf::from(c.as_os_str())).collect();
+    let mut hops = 0;
+
+    while let Some(component) = pending.pop_front() {
+        match component.components().next() {
+            None | Some(Component::CurDir) => {}
+            Some(Component::ParentDir) => {
+                if resolved.pop().is_none() {
+                    return false;
+                }
+            }
+            Some(Component::Normal(name)) => {
+                resolved.push(name.to_os_string());
+                let current: PathBuf = root.join(resolved.iter().collect::<PathBuf>());
+
+                let Ok(target) = fs::read_link(&current) else {
+                    continue;
+                };
+
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS || target.has_root() {
+                    return false;
+                }
+
+                // The symlink is replaced by its target, resolved from the directory containing it
+                resolved.pop();
+                for target_component in target.components().rev() {
+                    pending.push_front(PathBuf::from(target_component.as_os_str()));
+                }
+            }
+            Some(Component::RootDir | Component::Prefix(_)) => return false,
+        }
+    }
+
+    true
+}
@@ -0,0 +1,308 @@
+//! Contains 7z-specific building and unpacking functions
+
+use std::{
+    env,
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use filetime::{set_file_mtime, FileTime};
+use fs_err as fs;
+use same_file::Handle;
+use sevenz_rust::SevenZArchiveEntry;
+use ubyte::ToByteUnit;
+
+use super::read_link_target;
+use crate::{
+    error::FinalError,
+    info,
+    list::{EntryKind, FileInArchive},
+    progress::Progress,
+    utils::{
+        self, cd_into_same_dir_as, get_invalid_utf8_paths, pretty_format_list_of_paths, strip_cur_dir, EntryFilter,
+        EntryLookup, EscapedPathDisplay, FileVisibilityPolicy,
+    },
+    warning,
+};
+
+/// Unpacks the archive given by `reader` into the folder given by `output_folder`,
+/// skipping entries not selected by `entry_filter`.
+/// Assumes that output_folder is empty
+pub fn unpack_archive<R>(
+    reader: R,
+    output_folder: &Path,
+    entry_filter: &EntryFilter,
+    quiet: bool,
+) -> crate::Result<usize>
+where
+    R: Read + Seek,
+{
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut unpacked_files = 0;
+
+    sevenz_rust::decompress_with_extract_fn(reader, output_folder, |entry, reader, _| {
+        let Some(file_path) = enclosed_name(entry.name()) else {
+            warning!("Skipping 7z entry with unsafe path '{}'.", entry.name());
+            return Ok(true);
+        };
+
+        if !entry_filter.is_selected(&file_path) {
+            return Ok(true);
+        }
+
+        let file_path = output_folder.join(file_path);
+
+        if entry.is_directory() {
+            // This is printed for every file in the archive and has little
+            // importance for most users, but would generate lots of
+            // spoken text for users using screen readers, braille displays
+            // and so on
+            if !quiet {
+                info!(
+                    inaccessible,
+                    "File {} extracted to \"{}\"",
+                    entry.name(),
+                    file_path.display()
+                );
+            }
+            fs::create_dir_all(&file_path)?;
+        } else {
+            if let Some(path) = file_path.parent() {
+                if !path.exists() {
+                    fs::create_dir_all(path)?;
+                }
+            }
+            let file_path = strip_cur_dir(file_path.as_path());
+
+            // same reason is in the directory case: long, often not needed text
+            if !quiet {
+                info!(
+                    inaccessible,
+                    "{:?} extracted. ({})",
+                    file_path.display(),
+                    entry.size().bytes()
+                );
+            }
+
+            let mut output_file = fs::File::create(file_path)?;
+            io::copy(reader, &mut output_file)?;
+
+            set_last_modified_time(entry, file_path)?;
+        }
+
+        unpacked_files += 1;
+
+        Ok(true)
+    })?;
+
+    Ok(unpacked_files)
+}
+
+/// Reads every entry of the archive given by `reader` without writing anything to disk,
+/// failing if the archive is corrupted
+pub fn test_archive<R>(mut reader: R) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    let len = reader.seek(io::SeekFrom::End(0))?;
+    reader.rewind()?;
+    let mut archive = sevenz_rust::SevenZReader::new(reader, len, sevenz_rust::Password::empty())?;
+
+    // Entries with a stored CRC are checked by the reader once fully read
+    archive.for_each_entries(|_, reader| {
+        io::copy(reader, &mut io::sink())?;
+        Ok(true)
+    })?;
+
+    Ok(())
+}
+
+/// Writes the content of the file entry at `entry_path` to `writer`
+pub fn cat_entry<R>(mut reader: R, entry_path: &Path, writer: &mut dyn Write) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    let len = reader.seek(io::SeekFrom::End(0))?;
+    reader.rewind()?;
+    let mut archive = sevenz_rust::SevenZReader::new(reader, len, sevenz_rust::Password::empty())?;
+    let mut lookup = EntryLookup::new(entry_path);
+    let mut found = false;
+    // Target of the wanted entry if it's a symlink, which has no content of its own to print
+    let mut link_target = None;
+
+    // Returning `false` only stops the current block, so `found` is also checked for the following ones
+    archive.for_each_entries(|entry, reader| {
+        if found {
+            return Ok(false);
+        }
+
+        if entry.is_directory() {
+            return Ok(true);
+        }
+
+        if enclosed_name(entry.name()).is_some_and(|path| lookup.matches(&path)) {
+            // Like zip, 7z stores the target of a symlink as the contents of the entry, and its
+            // unix mode in the upper bits of the attributes
+            let unix_mode = entry.windows_attributes >> 16;
+            if entry.has_windows_attributes && unix_mode & 0o170000 == 0o120000 {
+                link_target = Some(read_link_target(reader, entry.name()));
+            } else {
+                io::copy(reader, writer)?;
+            }
+            found = true;
+        }
+
+        Ok(!found)
+    })?;
+
+    match (found, link_target) {
+        (true, Some(target)) => Err(lookup.link_error(Path::new(&target?))),
+        (true, None) => Ok(()),
+        (false, _) => Err(lookup.not_found_error()),
+    }
+}
+
+/// List contents of `reader`, returning a vector of archive entries
+pub fn list_archive<R>(mut reader: R) -> crate::Result<impl Iterator<Item = crate::Result<FileInArchive>>>
+where
+    R: Read + Seek,
+{
+    // 7z stores all entry metadata in a header at the end of the archive, so reading it
+    // does not require decompressing any of the entries
+    let len = reader.seek(io::SeekFrom::End(0))?;
+    reader.rewind()?;
+    let archive = sevenz_rust::Archive::read(&mut reader, len, &[])?;
+
+    let files = archive.files.into_iter().filter_map(|entry| {
+        let path = enclosed_name(entry.name())?;
+        let kind = if entry.is_directory() {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        };
+
+        Some(Ok(FileInArchive {
+            path,
+            kind,
+            size: Some(entry.size()),
+            mtime: entry
+                .has_last_modified_date
+                .then(|| entry.last_modified_date().to_unix_time()),
+            ..Default::default()
+        }))
+    });
+
+    Ok(files)
+}
+
+/// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+pub fn build_archive_from_paths<W>(
+    input_filenames: &[PathBuf],
+    output_path: &Path,
+    writer: W,
+    file_visibility_policy: FileVisibilityPolicy,
+    progress: &Progress,
+    quiet: bool,
+) -> crate::Result<W>
+where
+    W: Write + Seek,
+{
+    let mut writer = sevenz_rust::SevenZWriter::new(writer)?;
+    let output_handle = Handle::from_path(output_path);
+
+    // Vec of any filename that failed the UTF-8 check
+    let invalid_unicode_filenames = get_invalid_utf8_paths(input_filenames);
+
+    if !invalid_unicode_filenames.is_empty() {
+        let error = FinalError::with_title("Cannot build 7z archive")
+            .detail("7z archives require files to have valid UTF-8 paths")
+            .detail(format!(
+                "Files with invalid paths: {}",
+                pretty_format_list_of_paths(&invalid_unicode_filenames)
+            ));
+
+        return Err(error.into());
+    }
+
+    for filename in input_filenames {
+        let previous_location = cd_into_same_dir_as(filename)?;
+
+        // Safe unwrap, input shall be treated before
+        let filename = filename.file_name().unwrap();
+
+        for entry in file_visibility_policy.build_walker(filename) {
+            let entry = entry?;
+            let path = entry.path();
+
+            // If the output_path is the same as the input file, warn the user and skip the input (in order to avoid compression recursion)
+            if let Ok(ref handle) = output_handle {
+                if matches!(Handle::from_path(path), Ok(x) if &x == handle) {
+                    warning!(
+                        "The output file and the input file are the same: `{}`, skipping...",
+                        output_path.display()
+                    );
+                    continue;
+                }
+            }
+
+            // This is printed for every file in `input_filenames` and has
+            // little importance for most users, but would generate lots of
+            // spoken text for users using screen readers, braille displays
+            // and so on
+            if !quiet {
+                info!(inaccessible, "Compressing '{}'.", EscapedPathDisplay::new(path));
+            }
+
+            let metadata = match path.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::NotFound && utils::is_symlink(path) {
+                        // This path is for a broken symlink
+                        // We just ignore it
+                        continue;
+                    }
+                    return Err(e.into());
+                }
+            };
+
+            let entry = SevenZArchiveEntry::from_path(path, path.to_str().unwrap().to_owned());
+
+            if metadata.is_dir() {
+                writer.push_archive_entry::<fs::File>(entry, None)?;
+            } else {
+                let file = progress.reader(fs::File::open(path)?);
+                writer.push_archive_entry(entry, Some(file))?;
+            }
+        }
+
+        env::set_current_dir(previous_location)?;
+    }
+
+    let bytes = writer.finish()?;
+    Ok(bytes)
+}
+
+/// Returns the entry name as a relative path, or `None` if it would escape the output folder.
+///
+/// Mirrors the behavior of `zip::read::ZipFile::enclosed_name`.
+fn enclosed_name(name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(name);
+
+    let is_enclosed = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+    is_enclosed.then_some(path)
+}
+
+fn set_last_modified_time(entry: &SevenZArchiveEntry, path: &Path) -> io::Result<()> {
+    if !entry.has_last_modified_date {
+        return Ok(());
+    }
+
+    let modification_time = SystemTime::from(entry.last_modified_date());
+
+    set_file_mtime(path, FileTime::from_system_time(modification_time))
+}
@@ -1,35 +1,76 @@
 //! Contains Tar-specific building and unpacking functions
 
 use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
     env,
-    io::prelude::*,
+    io::{self, prelude::*},
     path::{Path, PathBuf},
     sync::mpsc::{self, Receiver},
     thread,
+    time::UNIX_EPOCH,
 };
 
+#[cfg(unix)]
+use std::collections::hash_map::Entry;
+
 use fs_err as fs;
 use same_file::Handle;
 use ubyte::ToByteUnit;
 
+use super::{read_link_target, safe_extract::SafeExtractor};
 use crate::{
     error::FinalError,
     info,
-    list::FileInArchive,
-    utils::{self, EscapedPathDisplay, FileVisibilityPolicy},
+    list::{EntryKind, FileInArchive},
+    progress::Progress,
+    utils::{self, EntryFilter, EntryLookup, EscapedPathDisplay, FileVisibilityPolicy, LimitTracker, Reproducible},
     warning,
 };
 
-/// Unpacks the archive given by `archive` into the folder given by `into`.
+/// Unpacks the archive given by `archive` into the folder given by `into`,
+/// skipping entries not selected by `entry_filter`, and failing once a limit of `limits` is exceeded.
 /// Assumes that output_folder is empty
-pub fn unpack_archive(reader: Box<dyn Read>, output_folder: &Path, quiet: bool) -> crate::Result<usize> {
+pub fn unpack_archive(
+    reader: Box<dyn Read>,
+    output_folder: &Path,
+    entry_filter: &EntryFilter,
+    limits: &LimitTracker,
+    quiet: bool,
+) -> crate::Result<usize> {
     assert!(output_folder.read_dir().expect("dir exists").count() == 0);
-    let mut archive = tar::Archive::new(reader);
+    // The whole stream is counted, entries that are skipped still have to be decompressed
+    let mut archive = tar::Archive::new(limits.limit_output(reader));
+    let mut extractor = SafeExtractor::new(output_folder)?;
 
     let mut files_unpacked = 0;
     for file in archive.entries()? {
         let mut file = file?;
+        let path = file.path()?.into_owned();
+
+        if !entry_filter.is_selected(&path) {
+            continue;
+        }
+
+        limits.add_entry()?;
 
+        if file.header().entry_type().is_symlink() {
+            // Symlinks are created at the end, once nothing else can be written through them
+            if let Some(target) = file.link_name()? {
+                extractor.add_symlink(&path, &target);
+            }
+            continue;
+        }
+
+        if extractor.destination(&path).is_none() {
+            continue;
+        }
+        if file.header().entry_type() == tar::EntryType::Link {
+            match file.link_name()? {
+                Some(target) if extractor.accepts_hardlink(&path, &target) => {}
+                _ => continue,
+            }
+        }
         file.unpack_in(output_folder)?;
 
         // This is printed for every file in the archive and has little
@@ -43,14 +84,80 @@ pub fn unpack_archive(reader: Box<dyn Read>, output_folder: &Path, quiet: bool)
                 utils::strip_cur_dir(&output_folder.join(file.path()?)),
                 file.size().bytes(),
             );
-
-            files_unpacked += 1;
         }
+
+        files_unpacked += 1;
     }
 
+    files_unpacked += extractor.finish()?;
+
     Ok(files_unpacked)
 }
 
+/// Reads every entry of the archive given by `reader` without writing anything to disk,
+/// failing if the archive is corrupted
+pub fn test_archive(reader: Box<dyn Read>) -> crate::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for file in archive.entries()? {
+        let mut file = file?;
+        io::copy(&mut file, &mut io::sink())?;
+    }
+
+    Ok(())
+}
+
+/// Writes the content of the file entry at `entry_path` to `writer`
+///
+/// Hard links have no data, it's stored under the path of the entry they link to, earlier in the archive, so
+/// the archive is opened again with `reopen` to print it.
+pub fn cat_entry(
+    reader: Box<dyn Read>,
+    entry_path: &Path,
+    writer: &mut dyn Write,
+    reopen: &dyn Fn() -> crate::Result<Box<dyn Read>>,
+) -> crate::Result<()> {
+    let Some(target) = cat_file_entry(reader, entry_path, writer)? else {
+        return Ok(());
+    };
+
+    match cat_file_entry(reopen()?, &target, writer)? {
+        None => Ok(()),
+        Some(next_target) => Err(EntryLookup::new(&target).link_error(&next_target)),
+    }
+}
+
+/// Writes the content of the file entry at `entry_path` to `writer`, or returns the target of the hard link
+/// at that path, see `cat_entry`
+fn cat_file_entry(reader: Box<dyn Read>, entry_path: &Path, writer: &mut dyn Write) -> crate::Result<Option<PathBuf>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut lookup = EntryLookup::new(entry_path);
+
+    for file in archive.entries()? {
+        let mut file = file?;
+
+        if file.header().entry_type().is_dir() {
+            continue;
+        }
+
+        if lookup.matches(&file.path()?) {
+            let entry_type = file.header().entry_type();
+            let target = file.link_name()?.unwrap_or_default().into_owned();
+            if entry_type.is_symlink() {
+                return Err(lookup.link_error(&target));
+            }
+            if entry_type.is_hard_link() {
+                return Ok(Some(target));
+            }
+
+            io::copy(&mut file, writer)?;
+            return Ok(None);
+        }
+    }
+
+    Err(lookup.not_found_error())
+}
+
 /// List contents of `archive`, returning a vector of archive entries
 pub fn list_archive(
     mut archive: tar::Archive<impl Read + Send + 'static>,
@@ -69,9 +176,24 @@ pub fn list_archive(
         for file in archive.entries().expect("entries is only used once") {
             let file_in_archive = (|| {
                 let file = file?;
-                let path = file.path()?.into_owned();
-                let is_dir = file.header().entry_type().is_dir();
-                Ok(FileInArchive { path, is_dir })
+                let header = file.header();
+                let kind = match header.entry_type() {
+                    tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+                    tar::EntryType::Directory => EntryKind::Directory,
+                    tar::EntryType::Symlink => EntryKind::Symlink,
+                    tar::EntryType::Link => EntryKind::Hardlink,
+                    _ => EntryKind::Other,
+                };
+
+                Ok(FileInArchive {
+                    path: file.path()?.into_owned(),
+                    kind,
+                    size: header.size().ok(),
+                    compressed_size: None,
+                    mode: header.mode().ok(),
+                    mtime: header.mtime().ok().map(|mtime| mtime as i64),
+                    link_target: file.link_name()?.map(Cow::into_owned),
+                })
             })();
             tx.send(file_in_archive).unwrap();
         }
@@ -81,18 +203,60 @@ pub fn list_archive(
 }
 
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// Unless `follow_symlinks` is set, symlinks are stored as symlink entries, and files reachable
+/// through several hardlinks are stored once, the other paths become hardlink entries.
+#[allow(clippy::too_many_arguments)]
 pub fn build_archive_from_paths<W>(
     input_filenames: &[PathBuf],
     output_path: &Path,
     writer: W,
     file_visibility_policy: FileVisibilityPolicy,
+    follow_symlinks: bool,
+    reproducible: Option<Reproducible>,
+    progress: &Progress,
     quiet: bool,
 ) -> crate::Result<W>
 where
     W: Write,
 {
     let mut builder = tar::Builder::new(writer);
+    append_paths(
+        &mut builder,
+        input_filenames,
+        output_path,
+        &file_visibility_policy,
+        follow_symlinks,
+        reproducible,
+        &|_| true,
+        progress,
+        quiet,
+    )?;
+
+    Ok(builder.into_inner()?)
+}
+
+/// Appends the files given by `input_filenames` to `builder` like `build_archive_from_paths` does,
+/// leaving out the paths rejected by `select`
+#[allow(clippy::too_many_arguments)]
+pub fn append_paths<W>(
+    builder: &mut tar::Builder<W>,
+    input_filenames: &[PathBuf],
+    output_path: &Path,
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+    reproducible: Option<Reproducible>,
+    select: &dyn Fn(&Path) -> bool,
+    progress: &Progress,
+    quiet: bool,
+) -> crate::Result<()>
+where
+    W: Write,
+{
     let output_handle = Handle::from_path(output_path);
+    // First path archived for each file with several hardlinks, by device and inode
+    #[cfg(unix)]
+    let mut hardlinks: HashMap<(u64, u64), PathBuf> = HashMap::new();
 
     for filename in input_filenames {
         let previous_location = utils::cd_into_same_dir_as(filename)?;
@@ -115,6 +279,10 @@ where
                 }
             }
 
+            if !select(path) {
+                continue;
+            }
+
             // This is printed for every file in `input_filenames` and has
             // little importance for most users, but would generate lots of
             // spoken text for users using screen readers, braille displays
@@ -123,8 +291,36 @@ where
                 info!(inaccessible, "Compressing '{}'.", EscapedPathDisplay::new(path));
             }
 
+            if !follow_symlinks && entry.path_is_symlink() {
+                let mut header = header_from_metadata(&fs::symlink_metadata(path)?, reproducible);
+                builder.append_link(&mut header, path, fs::read_link(path)?)?;
+                continue;
+            }
+
+            #[cfg(unix)]
+            if !follow_symlinks {
+                use std::os::unix::fs::MetadataExt;
+
+                let metadata = fs::symlink_metadata(path)?;
+                if metadata.is_file() && metadata.nlink() > 1 {
+                    match hardlinks.entry((metadata.dev(), metadata.ino())) {
+                        Entry::Occupied(first_path) => {
+                            let mut header = header_from_metadata(&metadata, reproducible);
+                            header.set_entry_type(tar::EntryType::Link);
+                            header.set_size(0);
+                            builder.append_link(&mut header, path, first_path.get())?;
+                            continue;
+                        }
+                        Entry::Vacant(vacant) => {
+                            vacant.insert(path.to_path_buf());
+                        }
+                    }
+                }
+            }
+
             if path.is_dir() {
-                builder.append_dir(path, path)?;
+                let mut header = header_from_metadata(&fs::metadata(path)?, reproducible);
+                builder.append_data(&mut header, path, io::empty())?;
             } else {
                 let mut file = match fs::File::open(path) {
                     Ok(f) => f,
@@ -137,15 +333,174 @@ where
                         return Err(e.into());
                     }
                 };
-                builder.append_file(path, file.file_mut()).map_err(|err| {
-                    FinalError::with_title("Could not create archive")
-                        .detail("Unexpected error while trying to read file")
-                        .detail(format!("Error: {err}."))
-                })?;
+                let mut header = header_from_metadata(&file.metadata()?, reproducible);
+                builder
+                    .append_data(&mut header, path, progress.reader(file.file_mut()))
+                    .map_err(|err| {
+                        FinalError::with_title("Could not create archive")
+                            .detail("Unexpected error while trying to read file")
+                            .detail(format!("Error: {err}."))
+                    })?;
             }
         }
         env::set_current_dir(previous_location)?;
     }
 
+    Ok(())
+}
+
+/// Copies the entries of `archive` to `builder`, leaving out the paths rejected by `keep`
+///
+/// Hard links store no data, it's under the path of the entry they link to, given in `link_targets` (see
+/// `hard_link_targets`). When that entry is left out, its data is kept in a temporary file until the first
+/// link to it that is kept, which is written as a regular file, and the next links point to it instead.
+pub fn copy_entries<R, W>(
+    mut archive: tar::Archive<R>,
+    builder: &mut tar::Builder<W>,
+    keep: &dyn Fn(&Path) -> bool,
+    link_targets: &HashSet<PathBuf>,
+) -> crate::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    // Headers and data of the link targets that were left out, until a link to them is kept
+    let mut left_out_targets: HashMap<PathBuf, (tar::Header, std::fs::File)> = HashMap::new();
+    // Where the data of the link targets that were left out was written instead
+    let mut moved_targets: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // The paths are set again, in case they are too long for the header and need extension entries
+        let mut header = entry.header().clone();
+        let entry_type = header.entry_type();
+
+        if !keep(&path) {
+            if entry_type.is_file() && link_targets.contains(&path) {
+                let mut file = tempfile::tempfile()?;
+                io::copy(&mut entry, &mut file)?;
+                file.rewind()?;
+                left_out_targets.insert(path, (header, file));
+            }
+            continue;
+        }
+
+        match entry.link_name()?.map(Cow::into_owned) {
+            Some(target) if entry_type.is_hard_link() => {
+                if let Some(moved) = moved_targets.get(&target) {
+                    builder.append_link(&mut header, &path, moved)?;
+                } else if let Some((mut header, file)) = left_out_targets.remove(&target) {
+                    builder.append_data(&mut header, &path, file)?;
+                    moved_targets.insert(target, path);
+                } else {
+                    builder.append_link(&mut header, &path, target)?;
+                }
+            }
+            Some(target) if entry_type.is_symlink() => {
+                builder.append_link(&mut header, &path, target)?;
+            }
+            _ => builder.append_data(&mut header, &path, &mut entry)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Paths of the entries that hard links of `archive` point to, see `copy_entries`
+pub fn hard_link_targets<R: Read>(mut archive: tar::Archive<R>) -> crate::Result<HashSet<PathBuf>> {
+    let mut targets = HashSet::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_hard_link() {
+            if let Some(target) = entry.link_name()? {
+                targets.insert(target.into_owned());
+            }
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Header for an entry with `metadata`, without its path, see `Reproducible` for the fields it changes
+fn header_from_metadata(metadata: &std::fs::Metadata, reproducible: Option<Reproducible>) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+
+    match reproducible {
+        None => header.set_metadata(metadata),
+        Some(reproducible) => {
+            // Zeroes the owners and only keeps the executable bit of the permissions
+            header.set_metadata_in_mode(metadata, tar::HeaderMode::Deterministic);
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_secs());
+            header.set_mtime(reproducible.mtime(mtime));
+        }
+    }
+
+    header
+}
+
+/// Converts the zip archive given by `archive` into a tar archive written to `writer`, entry by entry,
+/// keeping their paths, permissions and modification times.
+pub fn build_archive_from_zip<R, W>(mut archive: zip::ZipArchive<R>, writer: W, quiet: bool) -> crate::Result<W>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let mut builder = tar::Builder::new(writer);
+
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+
+        let Some(path) = file.enclosed_name().map(Path::to_owned) else {
+            warning!("Skipping zip entry with unsafe path '{}'.", file.name());
+            continue;
+        };
+
+        let mut header = tar::Header::new_gnu();
+        // Zip archives created outside of unix systems may not store permissions
+        let default_mode = if file.is_dir() { 0o755 } else { 0o644 };
+        header.set_mode(file.unix_mode().unwrap_or(default_mode) & 0o7777);
+        // Zip does not support timezones nor dates before 1980, so the conversion can't overflow
+        header.set_mtime(
+            file.last_modified()
+                .to_time()
+                .map_or(0, |time| time.unix_timestamp() as u64),
+        );
+
+        if file.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            builder.append_data(&mut header, &path, io::empty())?;
+        } else if file
+            .unix_mode()
+            .map(|mode| mode & 0o170000 == 0o120000)
+            .unwrap_or(false)
+        {
+            // Zip stores the target of a symlink as the contents of the entry
+            let target = read_link_target(&mut file, &path.to_string_lossy())?;
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, &path, target)?;
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(file.size());
+            builder.append_data(&mut header, &path, &mut file)?;
+        }
+
+        // This is printed for every entry in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        if !quiet {
+            info!(inaccessible, "Converted '{}'.", EscapedPathDisplay::new(&path));
+        }
+    }
+
     Ok(builder.into_inner()?)
 }
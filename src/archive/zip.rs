@@ -3,49 +3,101 @@
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::{
+    collections::{hash_map::Entry, HashMap},
     env,
-    io::{self, prelude::*},
+    io::{self, prelude::*, BufReader, BufWriter, SeekFrom},
     path::{Path, PathBuf},
-    sync::mpsc,
+    sync::{mpsc, Mutex},
     thread,
 };
 
 use filetime::{set_file_mtime, FileTime};
 use fs_err as fs;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use same_file::Handle;
 use time::OffsetDateTime;
 use ubyte::ToByteUnit;
 use zip::{self, read::ZipFile, DateTime, ZipArchive};
 
+use super::{read_link_target, safe_extract::SafeExtractor, MAX_LINK_TARGET_LEN};
 use crate::{
     error::FinalError,
     info,
-    list::FileInArchive,
+    list::{EntryKind, FileInArchive},
+    progress::Progress,
     utils::{
-        self, cd_into_same_dir_as, get_invalid_utf8_paths, pretty_format_list_of_paths, strip_cur_dir,
-        EscapedPathDisplay, FileVisibilityPolicy,
+        self, cd_into_same_dir_as, get_invalid_utf8_paths, pretty_format_list_of_paths, strip_cur_dir, EntryFilter,
+        EntryLookup, EscapedPathDisplay, FileVisibilityPolicy, LimitTracker, Reproducible,
     },
-    warning,
+    warning, BUFFER_CAPACITY,
 };
 
-/// Unpacks the archive given by `archive` into the folder given by `output_folder`.
+/// Signatures of the zip records read from streams, see `read_stream_entries`
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+/// Length of a local file header without its file name and extra field
+const LOCAL_FILE_HEADER_LEN: usize = 30;
+/// Flag of a local file header whose sizes and CRC32 are in a data descriptor, after the data
+const DATA_DESCRIPTOR_FLAG: u16 = 1 << 3;
+
+/// Unpacks the archive opened by `open_archive` into the folder given by `output_folder`,
+/// skipping entries not selected by `entry_filter`, and failing once a limit of `limits` is exceeded.
 /// Assumes that output_folder is empty
-pub fn unpack_archive<R>(mut archive: ZipArchive<R>, output_folder: &Path, quiet: bool) -> crate::Result<usize>
+///
+/// Zip entries are compressed independently, so files are inflated in parallel on rayon's pool, each
+/// thread reading through its own archive from `open_archive`. Directories are created before that,
+/// and get their permissions and modification times once every file was written.
+pub fn unpack_archive<R, F>(
+    open_archive: F,
+    output_folder: &Path,
+    entry_filter: &EntryFilter,
+    limits: &LimitTracker,
+    quiet: bool,
+) -> crate::Result<usize>
 where
-    R: Read + Seek,
+    R: Read + Seek + Send,
+    F: Fn() -> crate::Result<ZipArchive<R>> + Sync + Send,
 {
     assert!(output_folder.read_dir().expect("dir exists").count() == 0);
 
-    let mut unpacked_files = 0;
+    let mut archive = open_archive()?;
+    let mut extractor = SafeExtractor::new(output_folder)?;
+    // Indexes of the entries and where they are extracted to
+    let mut directories = vec![];
+    let mut files = vec![];
+    // Position in `files` of each path, the last entry with a path is the one extracted, like when
+    // entries were extracted one after the other, instead of several threads writing the same file
+    let mut file_positions = HashMap::new();
 
     for idx in 0..archive.len() {
         let mut file = archive.by_index(idx)?;
         let file_path = match file.enclosed_name() {
             Some(path) => path.to_owned(),
-            None => continue,
+            None => {
+                warning!("Skipping zip entry with unsafe path '{}'.", file.name());
+                continue;
+            }
         };
 
-        let file_path = output_folder.join(file_path);
+        if !entry_filter.is_selected(&file_path) {
+            continue;
+        }
+
+        limits.add_entry()?;
+
+        // Zip stores the target of a symlink as the contents of the entry, they are only
+        // recreated on unix, elsewhere they are extracted as regular files, like other zip tools do
+        if cfg!(unix) && file.unix_mode().map(is_symlink_mode).unwrap_or(false) {
+            let target = read_link_target(&mut file, &file_path.to_string_lossy())?;
+            extractor.add_symlink(&file_path, Path::new(&target));
+            continue;
+        }
+
+        let file_path = match extractor.destination(&file_path) {
+            Some(path) => path,
+            None => continue,
+        };
 
         display_zip_comment_if_exists(&file);
 
@@ -59,6 +111,7 @@ where
                     info!(inaccessible, "File {} extracted to \"{}\"", idx, file_path.display());
                 }
                 fs::create_dir_all(&file_path)?;
+                directories.push((idx, file_path));
             }
             _is_file @ false => {
                 if let Some(path) = file_path.parent() {
@@ -66,32 +119,444 @@ where
                         fs::create_dir_all(path)?;
                     }
                 }
-                let file_path = strip_cur_dir(file_path.as_path());
-
-                // same reason is in _is_dir: long, often not needed text
-                if !quiet {
-                    info!(
-                        inaccessible,
-                        "{:?} extracted. ({})",
-                        file_path.display(),
-                        file.size().bytes()
-                    );
+                match file_positions.entry(file_path.clone()) {
+                    Entry::Occupied(position) => files[*position.get()] = (idx, file_path),
+                    Entry::Vacant(position) => {
+                        position.insert(files.len());
+                        files.push((idx, file_path));
+                    }
                 }
+            }
+        }
+    }
+
+    // One archive per thread of the pool, opened by the first file it extracts, each thread only
+    // locks its own so the locks are never contended. The last one is for the calling thread, when
+    // it's not in the pool, rayon may run small jobs on it
+    let pool_threads = rayon::current_num_threads();
+    let archives: Vec<Mutex<Option<ZipArchive<R>>>> = (0..=pool_threads).map(|_| Mutex::new(None)).collect();
+
+    files.par_iter().try_for_each(|(idx, file_path)| {
+        let thread = rayon::current_thread_index().unwrap_or(pool_threads);
+        let mut archive = archives[thread].lock().unwrap();
+        let archive = match &mut *archive {
+            Some(archive) => archive,
+            unopened => unopened.insert(open_archive()?),
+        };
+        let mut file = archive.by_index(*idx)?;
+        let file_path = strip_cur_dir(file_path);
+
+        // same reason is in _is_dir: long, often not needed text
+        if !quiet {
+            info!(
+                inaccessible,
+                "{:?} extracted. ({})",
+                file_path.display(),
+                file.size().bytes()
+            );
+        }
+
+        let mut output_file = fs::File::create(file_path)?;
+        // The sizes declared in the archive can't be trusted, the decompressed bytes are counted instead
+        let compressed_size = file.compressed_size();
+        io::copy(
+            &mut limits.limit_entry_output(&mut file, compressed_size),
+            &mut output_file,
+        )?;
+
+        set_last_modified_time(file.last_modified(), file_path)?;
+        #[cfg(unix)]
+        unix_set_permissions(file_path, file.unix_mode())?;
+
+        crate::Result::Ok(())
+    })?;
+
+    let unpacked_links = extractor.finish()?;
+
+    // Writing the files and links changed the modification times of their directories, and a
+    // directory without write permissions would have rejected them
+    for (idx, dir_path) in &directories {
+        let dir = archive.by_index(*idx)?;
+        set_last_modified_time(dir.last_modified(), dir_path)?;
+        #[cfg(unix)]
+        unix_set_permissions(dir_path, dir.unix_mode())?;
+    }
+
+    Ok(directories.len() + files.len() + unpacked_links)
+}
+
+/// Unpacks the zip archive read from `reader`, which can't seek, like `unpack_archive` does
+///
+/// Entries are extracted one after the other while the archive is read, see `read_stream_entries`,
+/// with `spill_dir` holding the rest of the archive when an entry can't be read from its local
+/// header. Permissions and symlinks are only known once the central directory at the end of the
+/// archive is read, so symlinks are written as files holding their targets until then.
+pub fn unpack_archive_stream(
+    reader: impl Read,
+    output_folder: &Path,
+    spill_dir: &Path,
+    entry_filter: &EntryFilter,
+    limits: &LimitTracker,
+    quiet: bool,
+) -> crate::Result<usize> {
+    assert!(output_folder.read_dir().expect("dir exists").count() == 0);
+
+    let mut extractor = SafeExtractor::new(output_folder)?;
+    // Where entries are extracted to, with their raw names to find their modes in the central directory
+    let mut directories = vec![];
+    let mut files = vec![];
+    // Position in `files` of each path, entries with the same path overwrite the previous ones
+    let mut file_positions = HashMap::new();
+
+    // Covers what is stored in `spill_dir`, and what comes after the entries
+    let modes = read_stream_entries(limits.limit_archive(reader), Some(spill_dir), |file| {
+        let Some(entry_path) = file.enclosed_name().map(Path::to_owned) else {
+            warning!("Skipping zip entry with unsafe path '{}'.", file.name());
+            return Ok(());
+        };
+
+        if !entry_filter.is_selected(&entry_path) {
+            return Ok(());
+        }
+
+        limits.add_entry()?;
+
+        let Some(file_path) = extractor.destination(&entry_path) else {
+            return Ok(());
+        };
 
-                let mut output_file = fs::File::create(file_path)?;
-                io::copy(&mut file, &mut output_file)?;
+        display_zip_comment_if_exists(file);
 
-                set_last_modified_time(&file, file_path)?;
+        // Same reasons as in `unpack_archive`: long, often not needed text
+        if file.is_dir() {
+            if !quiet {
+                info!(inaccessible, "Directory \"{}\" extracted", file_path.display());
             }
+            fs::create_dir_all(&file_path)?;
+            directories.push((file_path, file.last_modified(), file.name_raw().to_owned()));
+            return Ok(());
         }
 
-        #[cfg(unix)]
-        unix_set_permissions(&file_path, &file)?;
+        if let Some(path) = file_path.parent() {
+            if !path.exists() {
+                fs::create_dir_all(path)?;
+            }
+        }
+        if !quiet {
+            info!(
+                inaccessible,
+                "{:?} extracted. ({})",
+                strip_cur_dir(&file_path).display(),
+                file.size().bytes()
+            );
+        }
+
+        let mut output_file = fs::File::create(&file_path)?;
+        // The sizes declared in the archive can't be trusted, the decompressed bytes are counted instead
+        let compressed_size = file.compressed_size();
+        io::copy(
+            &mut limits.limit_entry_output(&mut *file, compressed_size),
+            &mut output_file,
+        )?;
+        set_last_modified_time(file.last_modified(), &file_path)?;
+
+        let file = (entry_path, file_path, file.name_raw().to_owned());
+        match file_positions.entry(file.1.clone()) {
+            Entry::Occupied(position) => files[*position.get()] = file,
+            Entry::Vacant(position) => {
+                position.insert(files.len());
+                files.push(file);
+            }
+        }
+
+        Ok(())
+    })?;
 
+    let mut unpacked_files = 0;
+    for (entry_path, file_path, name) in &files {
+        let mode = modes.get(name).copied();
+
+        // Zip stores the target of a symlink as the contents of the entry, see `unpack_archive`
+        if cfg!(unix) && mode.map(is_symlink_mode).unwrap_or(false) {
+            let target = read_link_target(fs::File::open(file_path)?, &entry_path.to_string_lossy())?;
+            fs::remove_file(file_path)?;
+            extractor.add_symlink(entry_path, Path::new(&target));
+            continue;
+        }
+
+        #[cfg(unix)]
+        unix_set_permissions(file_path, mode)?;
         unpacked_files += 1;
     }
 
-    Ok(unpacked_files)
+    let unpacked_links = extractor.finish()?;
+
+    // Writing the files and links changed the modification times of their directories, and a
+    // directory without write permissions would have rejected them
+    for (dir_path, last_modified, _name) in &directories {
+        set_last_modified_time(*last_modified, dir_path)?;
+        #[cfg(unix)]
+        unix_set_permissions(dir_path, modes.get(_name).copied())?;
+    }
+
+    Ok(directories.len() + unpacked_files + unpacked_links)
+}
+
+/// Reads every entry of the zip archive read from `reader`, which can't seek, like `test_archive` does
+pub fn test_archive_stream(reader: impl Read, spill_dir: Option<&Path>) -> crate::Result<()> {
+    read_stream_entries(reader, spill_dir, |file| {
+        io::copy(file, &mut io::sink())?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Calls `read_entry` with each entry of the zip archive read from `reader`, in order, and returns the
+/// unix modes of the entries from the central directory, by raw name
+///
+/// Entries are read from their local headers, so the archive doesn't have to be stored. That's not possible
+/// for an entry whose sizes are only written after its data, in a data descriptor (tools writing zip
+/// archives to pipes do that), or when the archive doesn't start with an entry: the rest of the archive is
+/// then written to a temporary file in `spill_dir`, or in the system's temporary directory if `None`, and
+/// the remaining entries are read from it, see `spill_file`.
+fn read_stream_entries(
+    reader: impl Read,
+    spill_dir: Option<&Path>,
+    mut read_entry: impl FnMut(&mut ZipFile) -> crate::Result<()>,
+) -> crate::Result<HashMap<Vec<u8>, u32>> {
+    let mut reader = ZipStream {
+        inner: reader,
+        position: 0,
+        failed: false,
+    };
+    // Local headers of the entries read so far and their offsets, to find them again after spilling
+    let mut local_headers = vec![];
+
+    loop {
+        let start = reader.position;
+        let mut header = [0; LOCAL_FILE_HEADER_LEN];
+        reader.read_exact(&mut header[..4])?;
+
+        match u32::from_le_bytes([header[0], header[1], header[2], header[3]]) {
+            LOCAL_FILE_HEADER_SIGNATURE => reader.read_exact(&mut header[4..])?,
+            CENTRAL_DIRECTORY_HEADER_SIGNATURE => return read_central_directory(reader),
+            // An archive without entries
+            END_OF_CENTRAL_DIRECTORY_SIGNATURE => {
+                io::copy(&mut reader, &mut io::sink())?;
+                return Ok(HashMap::new());
+            }
+            _ => return spill_stream_entries(reader, &header[..4], start, &local_headers, spill_dir, read_entry),
+        }
+
+        if u16::from_le_bytes([header[6], header[7]]) & DATA_DESCRIPTOR_FLAG != 0 {
+            return spill_stream_entries(reader, &header, start, &local_headers, spill_dir, read_entry);
+        }
+
+        let mut entry_reader = io::Cursor::new(header).chain(&mut reader);
+        let Some(mut file) = zip::read::read_zipfile_from_stream(&mut entry_reader)? else {
+            unreachable!("the signature of a local file header was read");
+        };
+
+        let mut local_header = header.to_vec();
+        local_header.extend_from_slice(file.name_raw());
+        local_header.extend_from_slice(file.extra_data());
+        local_headers.push((start, local_header));
+
+        read_entry(&mut file)?;
+    }
+}
+
+/// Writes the rest of the archive read by `read_stream_entries`, starting with the `read` bytes at
+/// `start`, to a temporary file, and calls `read_entry` with the entries that weren't read yet
+///
+/// The entries already read are left as holes in the file, only their local headers are written back
+/// so that the archive can be opened, its offsets are unchanged.
+fn spill_stream_entries(
+    mut reader: impl Read,
+    read: &[u8],
+    start: u64,
+    local_headers: &[(u64, Vec<u8>)],
+    spill_dir: Option<&Path>,
+    mut read_entry: impl FnMut(&mut ZipFile) -> crate::Result<()>,
+) -> crate::Result<HashMap<Vec<u8>, u32>> {
+    let file = spill_file(spill_dir)?;
+
+    let mut writer = BufWriter::with_capacity(BUFFER_CAPACITY, file);
+    for (offset, local_header) in local_headers {
+        writer.seek(SeekFrom::Start(*offset))?;
+        writer.write_all(local_header)?;
+    }
+    writer.seek(SeekFrom::Start(start))?;
+    writer.write_all(read)?;
+    io::copy(&mut reader, &mut writer)?;
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+
+    let mut archive = ZipArchive::new(BufReader::with_capacity(BUFFER_CAPACITY, file))?;
+    let mut modes = HashMap::new();
+
+    for idx in 0..archive.len() {
+        let file = archive.by_index_raw(idx)?;
+        if let Some(mode) = file.unix_mode() {
+            modes.insert(file.name_raw().to_owned(), mode);
+        }
+
+        if file.header_start() >= start {
+            drop(file);
+            read_entry(&mut archive.by_index(idx)?)?;
+        }
+    }
+
+    Ok(modes)
+}
+
+/// Anonymous temporary file in `dir`, deleted once closed, or in the system's temporary directory when
+/// `dir` is `None` or can't be written to
+pub fn spill_file(dir: Option<&Path>) -> io::Result<std::fs::File> {
+    match dir {
+        Some(dir) => tempfile::tempfile_in(dir).or_else(|_| tempfile::tempfile()),
+        None => tempfile::tempfile(),
+    }
+}
+
+/// Reads the records of the central directory, whose first signature was already read from `reader`,
+/// and returns the unix mode of each entry by raw name
+fn read_central_directory(mut reader: impl Read) -> crate::Result<HashMap<Vec<u8>, u32>> {
+    let mut modes = HashMap::new();
+
+    loop {
+        // The central directory file header, after its signature
+        let mut record = [0; 42];
+        reader.read_exact(&mut record)?;
+        let field = |offset: usize| u16::from_le_bytes([record[offset], record[offset + 1]]);
+        let external_attributes = u32::from_le_bytes([record[34], record[35], record[36], record[37]]);
+
+        let mut name = vec![0; field(24) as usize];
+        reader.read_exact(&mut name)?;
+        // The extra field and the comment
+        io::copy(
+            &mut (&mut reader).take(field(26) as u64 + field(28) as u64),
+            &mut io::sink(),
+        )?;
+
+        if let Some(mode) = unix_mode(field(0), external_attributes) {
+            modes.insert(name, mode);
+        }
+
+        let mut signature = [0; 4];
+        reader.read_exact(&mut signature)?;
+        if u32::from_le_bytes(signature) != CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+            break;
+        }
+    }
+
+    // The end of central directory records, so that the formats wrapping the archive are read to their end
+    io::copy(&mut reader, &mut io::sink())?;
+
+    Ok(modes)
+}
+
+/// Unix mode of a central directory record, like `ZipFile::unix_mode` computes it
+fn unix_mode(version_made_by: u16, external_attributes: u32) -> Option<u32> {
+    if external_attributes == 0 {
+        return None;
+    }
+
+    match version_made_by >> 8 {
+        // Unix
+        3 => Some(external_attributes >> 16),
+        // MS-DOS, only has a directory and a read-only attribute
+        0 => {
+            let mut mode = if external_attributes & 0x10 != 0 {
+                0o040775
+            } else {
+                0o100664
+            };
+            if external_attributes & 0x01 != 0 {
+                mode &= 0o0555;
+            }
+            Some(mode)
+        }
+        _ => None,
+    }
+}
+
+/// Reader of a zip archive read by `read_stream_entries`, counting the bytes read to know where entries start
+///
+/// It ends after an error: entries read from a stream consume the rest of their data when dropped, and panic
+/// if that fails.
+struct ZipStream<R> {
+    inner: R,
+    position: u64,
+    failed: bool,
+}
+
+impl<R: Read> Read for ZipStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.failed {
+            return Ok(0);
+        }
+
+        match self.inner.read(buf) {
+            Ok(read) => {
+                self.position += read as u64;
+                Ok(read)
+            }
+            Err(err) => {
+                self.failed = err.kind() != io::ErrorKind::Interrupted;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Reads every entry of `archive` without writing anything to disk, failing if the archive
+/// is corrupted
+///
+/// The CRC32 of each entry is checked by the zip reader once the entry is fully read.
+pub fn test_archive<R>(mut archive: ZipArchive<R>) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        io::copy(&mut file, &mut io::sink())?;
+    }
+
+    Ok(())
+}
+
+/// Writes the content of the file entry at `entry_path` to `writer`
+pub fn cat_entry<R>(mut archive: ZipArchive<R>, entry_path: &Path, writer: &mut dyn Write) -> crate::Result<()>
+where
+    R: Read + Seek,
+{
+    let mut lookup = EntryLookup::new(entry_path);
+
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let Some(file_path) = file.enclosed_name() else {
+            continue;
+        };
+
+        if lookup.matches(file_path) {
+            if file.unix_mode().map(is_symlink_mode).unwrap_or(false) {
+                let file_path = file_path.to_owned();
+                let target = read_link_target(&mut file, &file_path.to_string_lossy())?;
+                return Err(lookup.link_error(Path::new(&target)));
+            }
+
+            io::copy(&mut file, writer)?;
+            return Ok(());
+        }
+    }
+
+    Err(lookup.not_found_error())
 }
 
 /// List contents of `archive`, returning a vector of archive entries
@@ -112,15 +577,40 @@ where
     thread::spawn(move || {
         for idx in 0..archive.len() {
             let maybe_file_in_archive = (|| {
-                let file = match archive.by_index(idx) {
+                let mut file = match archive.by_index(idx) {
                     Ok(f) => f,
                     Err(e) => return Some(Err(e.into())),
                 };
 
                 let path = file.enclosed_name()?.to_owned();
-                let is_dir = file.is_dir();
+                let mode = file.unix_mode();
+                let kind = if file.is_dir() {
+                    EntryKind::Directory
+                } else if mode.map(is_symlink_mode).unwrap_or(false) {
+                    EntryKind::Symlink
+                } else {
+                    EntryKind::File
+                };
 
-                Some(Ok(FileInArchive { path, is_dir }))
+                // Zip stores the target of a symlink as the contents of the entry
+                let link_target = if kind == EntryKind::Symlink {
+                    match read_link_target(&mut file, &path.to_string_lossy()) {
+                        Ok(target) => Some(PathBuf::from(target)),
+                        Err(err) => return Some(Err(err)),
+                    }
+                } else {
+                    None
+                };
+
+                Some(Ok(FileInArchive {
+                    path,
+                    kind,
+                    size: Some(file.size()),
+                    compressed_size: Some(file.compressed_size()),
+                    mode: mode.map(|mode| mode & 0o7777),
+                    mtime: file.last_modified().to_time().ok().map(|mtime| mtime.unix_timestamp()),
+                    link_target,
+                }))
             })();
             if let Some(file_in_archive) = maybe_file_in_archive {
                 tx.send(file_in_archive).unwrap();
@@ -131,26 +621,121 @@ where
     Files(rx)
 }
 
+/// Lists the zip archive read from `reader`, which can't seek, like `list_archive` does
+///
+/// Entries are listed from their local headers while the archive is read, see `read_stream_entries`, and get
+/// their permissions once the central directory is. Symlinks are only known then, so the contents of the
+/// entries small enough to hold a link target are kept until the end.
+pub fn list_archive_stream(reader: impl Read, spill_dir: Option<&Path>) -> crate::Result<Vec<FileInArchive>> {
+    // Listed entries, with their raw names to find their modes in the central directory
+    let mut entries = vec![];
+
+    let modes = read_stream_entries(reader, spill_dir, |file| {
+        let Some(path) = file.enclosed_name().map(Path::to_owned) else {
+            return Ok(());
+        };
+
+        let (kind, contents) = if file.is_dir() {
+            (EntryKind::Directory, None)
+        } else if file.size() <= MAX_LINK_TARGET_LEN {
+            let mut contents = vec![];
+            // The size declared in the archive can't be trusted, see `read_link_target`
+            (&mut *file).take(MAX_LINK_TARGET_LEN + 1).read_to_end(&mut contents)?;
+            (EntryKind::File, Some(contents))
+        } else {
+            (EntryKind::File, None)
+        };
+
+        let file_in_archive = FileInArchive {
+            path,
+            kind,
+            size: Some(file.size()),
+            compressed_size: Some(file.compressed_size()),
+            mode: None,
+            mtime: file.last_modified().to_time().ok().map(|mtime| mtime.unix_timestamp()),
+            link_target: None,
+        };
+        entries.push((file_in_archive, file.name_raw().to_owned(), contents));
+
+        Ok(())
+    })?;
+
+    entries
+        .into_iter()
+        .map(|(mut file, name, contents)| {
+            let mode = modes.get(&name).copied();
+            file.mode = mode.map(|mode| mode & 0o7777);
+
+            if file.kind == EntryKind::File && mode.map(is_symlink_mode).unwrap_or(false) {
+                let entry_name = file.path.to_string_lossy();
+                let target = match contents {
+                    Some(contents) => read_link_target(&contents[..], &entry_name)?,
+                    // The entry is longer than any target, which `read_link_target` reports
+                    None => read_link_target(io::repeat(0), &entry_name)?,
+                };
+                file.kind = EntryKind::Symlink;
+                file.link_target = Some(PathBuf::from(target));
+            }
+
+            Ok(file)
+        })
+        .collect()
+}
+
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+#[allow(clippy::too_many_arguments)]
 pub fn build_archive_from_paths<W>(
     input_filenames: &[PathBuf],
     output_path: &Path,
     writer: W,
     file_visibility_policy: FileVisibilityPolicy,
+    follow_symlinks: bool,
+    reproducible: Option<Reproducible>,
+    progress: &Progress,
     quiet: bool,
 ) -> crate::Result<W>
 where
     W: Write + Seek,
 {
     let mut writer = zip::ZipWriter::new(writer);
+    append_paths(
+        &mut writer,
+        input_filenames,
+        output_path,
+        &file_visibility_policy,
+        follow_symlinks,
+        reproducible,
+        &|_| true,
+        progress,
+        quiet,
+    )?;
+
+    let bytes = writer.finish()?;
+    Ok(bytes)
+}
+
+/// Appends the files given by `input_filenames` to `writer` like `build_archive_from_paths` does,
+/// leaving out the paths rejected by `select`
+#[allow(clippy::too_many_arguments)]
+pub fn append_paths<W>(
+    writer: &mut zip::ZipWriter<W>,
+    input_filenames: &[PathBuf],
+    output_path: &Path,
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+    reproducible: Option<Reproducible>,
+    select: &dyn Fn(&Path) -> bool,
+    progress: &Progress,
+    quiet: bool,
+) -> crate::Result<()>
+where
+    W: Write + Seek,
+{
     // always use ZIP64 to allow compression of files larger than 4GB
     // the format is widely supported and the extra 20B is negligible in most cases
     let options = zip::write::FileOptions::default().large_file(true);
     let output_handle = Handle::from_path(output_path);
 
-    #[cfg(not(unix))]
-    let executable = options.unix_permissions(0o755);
-
     // Vec of any filename that failed the UTF-8 check
     let invalid_unicode_filenames = get_invalid_utf8_paths(input_filenames);
 
@@ -186,6 +771,10 @@ where
                 }
             }
 
+            if !select(path) {
+                continue;
+            }
+
             // This is printed for every file in `input_filenames` and has
             // little importance for most users, but would generate lots of
             // spoken text for users using screen readers, braille displays
@@ -194,6 +783,29 @@ where
                 info!(inaccessible, "Compressing '{}'.", EscapedPathDisplay::new(path));
             }
 
+            if !follow_symlinks && entry.path_is_symlink() {
+                let target = fs::read_link(path)?;
+                let Some(target) = target.to_str() else {
+                    let error = FinalError::with_title("Cannot build zip archive")
+                        .detail("Zip archives require symlinks to have valid UTF-8 targets")
+                        .detail(format!(
+                            "Symlink with invalid target: {}",
+                            EscapedPathDisplay::new(path)
+                        ))
+                        .hint("Use --follow-symlinks to archive the file it points to instead");
+
+                    return Err(error.into());
+                };
+
+                let metadata = fs::symlink_metadata(path)?;
+                writer.add_symlink(
+                    path.to_str().unwrap(),
+                    target,
+                    options.last_modified_time(get_last_modified_time(&metadata, reproducible)),
+                )?;
+                continue;
+            }
+
             let metadata = match path.metadata() {
                 Ok(metadata) => metadata,
                 Err(e) => {
@@ -207,30 +819,130 @@ where
             };
 
             #[cfg(unix)]
-            let options = options.unix_permissions(metadata.permissions().mode());
+            let options = options.unix_permissions(match reproducible {
+                Some(reproducible) => reproducible.mode(metadata.permissions().mode(), metadata.is_dir()),
+                None => metadata.permissions().mode(),
+            });
+            #[cfg(not(unix))]
+            let options = match reproducible {
+                Some(reproducible) => options.unix_permissions(reproducible.mode(0o644, metadata.is_dir())),
+                None => options,
+            };
+            // Without it, the zip crate would store the current time
+            let options = options.last_modified_time(get_last_modified_time(&metadata, reproducible));
 
             if metadata.is_dir() {
                 writer.add_directory(path.to_str().unwrap().to_owned(), options)?;
             } else {
                 #[cfg(not(unix))]
                 let options = if is_executable::is_executable(path) {
-                    executable
+                    options.unix_permissions(0o755)
                 } else {
                     options
                 };
 
-                let mut file = fs::File::open(path)?;
-                writer.start_file(
-                    path.to_str().unwrap(),
-                    options.last_modified_time(get_last_modified_time(&file)),
-                )?;
-                io::copy(&mut file, &mut writer)?;
+                let mut file = progress.reader(fs::File::open(path)?);
+                writer.start_file(path.to_str().unwrap(), options)?;
+                io::copy(&mut file, writer)?;
             }
         }
 
         env::set_current_dir(previous_location)?;
     }
 
+    Ok(())
+}
+
+/// Copies the entries of `archive` to `writer` without recompressing them, leaving out the paths
+/// rejected by `keep`
+pub fn copy_entries<R, W>(
+    archive: &mut ZipArchive<R>,
+    writer: &mut zip::ZipWriter<W>,
+    keep: &dyn Fn(&Path) -> bool,
+) -> crate::Result<()>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    for idx in 0..archive.len() {
+        let file = archive.by_index_raw(idx)?;
+
+        if keep(Path::new(file.name())) {
+            writer.raw_copy_file(file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the tar archive given by `archive` into a zip archive written to `writer`, entry by entry,
+/// keeping their paths, permissions and modification times.
+pub fn build_archive_from_tar<R, W>(mut archive: tar::Archive<R>, writer: W, quiet: bool) -> crate::Result<W>
+where
+    R: Read,
+    W: Write + Seek,
+{
+    let mut writer = zip::ZipWriter::new(writer);
+    // always use ZIP64, see `build_archive_from_paths`
+    let options = zip::write::FileOptions::default().large_file(true);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        let Some(name) = path.to_str().map(str::to_owned) else {
+            let error = FinalError::with_title("Cannot build zip archive")
+                .detail("Zip archives require entries to have valid UTF-8 paths")
+                .detail(format!("Entry with invalid path: {}", EscapedPathDisplay::new(&path)));
+
+            return Err(error.into());
+        };
+
+        let header = entry.header();
+        let mut options = options.unix_permissions(header.mode()?);
+        if let Some(mtime) = header
+            .mtime()
+            .ok()
+            .and_then(|mtime| OffsetDateTime::from_unix_timestamp(mtime as i64).ok())
+            .and_then(|mtime| DateTime::try_from(mtime).ok())
+        {
+            options = options.last_modified_time(mtime);
+        }
+
+        let entry_type = header.entry_type();
+
+        if entry_type.is_dir() {
+            writer.add_directory(name, options)?;
+        } else if entry_type.is_file() {
+            writer.start_file(name, options)?;
+            io::copy(&mut entry, &mut writer)?;
+        } else if let (true, Some(target)) = (entry_type.is_symlink(), entry.link_name()?) {
+            let Some(target) = target.to_str() else {
+                warning!(
+                    "Skipping '{}', zip archives require symlinks to have valid UTF-8 targets.",
+                    EscapedPathDisplay::new(&path)
+                );
+                continue;
+            };
+            writer.add_symlink(name, target, options)?;
+        } else {
+            warning!(
+                "Skipping '{}', entries of type {:?} can't be converted to zip.",
+                EscapedPathDisplay::new(&path),
+                entry_type
+            );
+            continue;
+        }
+
+        // This is printed for every entry in the archive and has little
+        // importance for most users, but would generate lots of
+        // spoken text for users using screen readers, braille displays
+        // and so on
+        if !quiet {
+            info!(inaccessible, "Converted '{}'.", EscapedPathDisplay::new(&path));
+        }
+    }
+
     let bytes = writer.finish()?;
     Ok(bytes)
 }
@@ -252,16 +964,22 @@ fn display_zip_comment_if_exists(file: &ZipFile) {
     }
 }
 
-fn get_last_modified_time(file: &fs::File) -> DateTime {
-    file.metadata()
-        .and_then(|metadata| metadata.modified())
-        .ok()
-        .and_then(|time| DateTime::try_from(OffsetDateTime::from(time)).ok())
-        .unwrap_or_default()
+fn get_last_modified_time(metadata: &std::fs::Metadata, reproducible: Option<Reproducible>) -> DateTime {
+    let mut time = metadata
+        .modified()
+        .map_or(OffsetDateTime::UNIX_EPOCH, OffsetDateTime::from);
+
+    if let Some(reproducible) = reproducible {
+        let clamped = reproducible.mtime(time.unix_timestamp().max(0) as u64);
+        time = OffsetDateTime::from_unix_timestamp(clamped as i64).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    }
+
+    // Times before 1980 can't be stored in zip archives
+    DateTime::try_from(time).unwrap_or_default()
 }
 
-fn set_last_modified_time(zip_file: &ZipFile, path: &Path) -> crate::Result<()> {
-    let modification_time = zip_file.last_modified().to_time();
+fn set_last_modified_time(last_modified: DateTime, path: &Path) -> crate::Result<()> {
+    let modification_time = last_modified.to_time();
 
     let Ok(time_in_seconds) = modification_time else {
         return Ok(());
@@ -276,12 +994,17 @@ fn set_last_modified_time(zip_file: &ZipFile, path: &Path) -> crate::Result<()>
 }
 
 #[cfg(unix)]
-fn unix_set_permissions(file_path: &Path, file: &ZipFile) -> crate::Result<()> {
+fn unix_set_permissions(file_path: &Path, mode: Option<u32>) -> crate::Result<()> {
     use std::fs::Permissions;
 
-    if let Some(mode) = file.unix_mode() {
+    if let Some(mode) = mode {
         fs::set_permissions(file_path, Permissions::from_mode(mode))?;
     }
 
     Ok(())
 }
+
+/// Whether the unix mode of a zip entry has the file type bits of a symlink (`S_IFLNK`)
+fn is_symlink_mode(mode: u32) -> bool {
+    mode & 0o170000 == 0o120000
+}
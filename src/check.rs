@@ -10,9 +10,12 @@ use std::{
 
 use crate::{
     error::FinalError,
-    extension::{build_archive_file_suggestion, Extension},
+    extension::{build_archive_file_suggestion, flatten_compression_formats, CompressionFormat, Extension},
     info,
-    utils::{pretty_format_list_of_paths, try_infer_extension, user_wants_to_continue, EscapedPathDisplay},
+    utils::{
+        format_options, is_stdio_path, pretty_format_list_of_paths, try_infer_extension, user_wants_to_continue,
+        EscapedPathDisplay,
+    },
     warning, QuestionAction, QuestionPolicy, Result,
 };
 
@@ -39,7 +42,9 @@ pub fn check_mime_type(
                 path.display(),
                 detected_format
             );
-            if user_wants_to_continue(path, question_policy, QuestionAction::Decompression)? {
+            // Input read from stdin has no name to take the extensions from, so inferring is expected, and
+            // questions can't be answered because stdin is already taken by the input
+            if is_stdio_path(path) || user_wants_to_continue(path, question_policy, QuestionAction::Decompression)? {
                 formats.push(detected_format);
             } else {
                 return Ok(ControlFlow::Break(()));
@@ -96,6 +101,65 @@ pub fn check_for_non_archive_formats(files: &[PathBuf], formats: &[Vec<Extension
     Ok(())
 }
 
+/// In the context of decompression, this function checks if entry patterns were given
+/// for a compressed file that is not an archive, which has no entries to select
+pub fn check_entry_filter_targets_archives(files: &[PathBuf], formats: &[Vec<Extension>]) -> Result<()> {
+    let not_archives: Vec<_> = files
+        .iter()
+        .zip(formats)
+        .filter(|(_, formats)| !formats.first().map(Extension::is_archive).unwrap_or(false))
+        .map(|(path, _)| path)
+        .collect();
+
+    if !not_archives.is_empty() {
+        let error = FinalError::with_title("Cannot select entries to extract")
+            .detail("Only archives have entries that can be selected with --entry")
+            .detail(format!(
+                "Files are not archives: {}",
+                pretty_format_list_of_paths(&not_archives)
+            ))
+            .hint("Remove the --entry patterns to decompress these files");
+
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+/// Check that decompression limits can be enforced for every file, they aren't for 7z and RAR archives
+pub fn check_limits_support_formats(files: &[PathBuf], formats: &[Vec<Extension>]) -> Result<()> {
+    let unsupported: Vec<_> = files
+        .iter()
+        .zip(formats)
+        .filter(|(_, formats)| {
+            formats
+                .first()
+                .map(|format| {
+                    matches!(
+                        format.compression_formats,
+                        [CompressionFormat::SevenZip | CompressionFormat::Rar, ..]
+                    )
+                })
+                .unwrap_or(false)
+        })
+        .map(|(path, _)| path)
+        .collect();
+
+    if !unsupported.is_empty() {
+        let error = FinalError::with_title("Cannot enforce decompression limits")
+            .detail("--max-size, --max-entries and --max-ratio are only supported for tar, zip and single file formats")
+            .detail(format!(
+                "Files that are 7z or rar archives: {}",
+                pretty_format_list_of_paths(&unsupported)
+            ))
+            .hint("Remove the limits to decompress these files");
+
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
 /// Show error if archive format is not the first format in the chain.
 pub fn check_archive_formats_position(formats: &[Extension], output_path: &Path) -> Result<()> {
     if let Some(format) = formats.iter().skip(1).find(|format| format.is_archive()) {
@@ -121,6 +185,84 @@ pub fn check_archive_formats_position(formats: &[Extension], output_path: &Path)
     Ok(())
 }
 
+/// Show error if one of the formats can only be decompressed, like RAR.
+pub fn check_formats_support_compression(formats: &[Extension], output_path: &Path) -> Result<()> {
+    let is_decompression_only = |format: &Extension| format.compression_formats.contains(&CompressionFormat::Rar);
+
+    if let Some(format) = formats.iter().find(|format| is_decompression_only(format)) {
+        let error = FinalError::with_title(format!(
+            "Cannot compress to '{}'.",
+            EscapedPathDisplay::new(output_path)
+        ))
+        .detail(format!("The format '{format}' can only be decompressed and listed."))
+        .detail("RAR is a proprietary format, ouch is not able to create RAR archives.")
+        .hint("Try using an open archive format instead, such as '.zip', '.7z' or '.tar.gz'.");
+
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// Show error if options were given with --opt for formats that the output doesn't use
+pub fn check_format_options_apply(formats: &[Extension], output_path: &Path) -> Result<()> {
+    let formats = flatten_compression_formats(formats);
+    let unused: Vec<&str> = format_options().unused_by(&formats).collect();
+
+    if !unused.is_empty() {
+        let error = FinalError::with_title(format!(
+            "Cannot compress to '{}'.",
+            EscapedPathDisplay::new(output_path)
+        ))
+        .detail(format!(
+            "These options don't apply to its formats: {}",
+            unused.join(", ")
+        ))
+        .hint("Options only apply to the format they start with, like zstd.long to '.zst'.");
+
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// Check that the archive can be created by --reproducible, 7z archives always store the current time
+pub fn check_reproducible_support_formats(formats: &[Extension], output_path: &Path) -> Result<()> {
+    if formats
+        .iter()
+        .any(|format| format.compression_formats.contains(&CompressionFormat::SevenZip))
+    {
+        let error = FinalError::with_title(format!(
+            "Cannot create '{}' reproducibly.",
+            EscapedPathDisplay::new(output_path)
+        ))
+        .detail("--reproducible is only supported for tar, zip and single file formats")
+        .hint("Try using '.tar.gz' or '.zip' instead, or remove --reproducible.");
+
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// Check that the archive can be modified in place, which is only supported for tar archives,
+/// compressed or not, and zip archives
+pub fn check_archive_can_be_modified(formats: &[Extension], archive_path: &Path) -> Result<()> {
+    use CompressionFormat::*;
+
+    let is_supported = match flatten_compression_formats(formats).as_slice() {
+        [Tar, rest @ ..] => !rest.iter().any(|format| matches!(format, Tar | Zip | SevenZip | Rar)),
+        [Zip] => true,
+        _ => false,
+    };
+
+    if !is_supported {
+        let error = FinalError::with_title(format!("Cannot modify '{}'.", EscapedPathDisplay::new(archive_path)))
+            .detail("Only tar archives, compressed or not, and zip archives can be modified")
+            .hint("Decompress the archive and compress it again with the changes instead.");
+
+        return Err(error.into());
+    }
+    Ok(())
+}
+
 /// Check if all provided files have formats to decompress.
 pub fn check_missing_formats_when_decompressing(files: &[PathBuf], formats: &[Vec<Extension>]) -> Result<()> {
     let files_missing_format: Vec<PathBuf> = files
@@ -167,6 +309,19 @@ pub fn check_first_format_when_compressing<'a>(formats: &'a [Extension], output_
     })
 }
 
+/// Show error if compressing to stdout without `--format`, as there is no file name to detect the formats from.
+pub fn check_format_flag_when_writing_to_stdout(formats_from_flag: Option<&OsString>) -> Result<()> {
+    if formats_from_flag.is_none() {
+        let error = FinalError::with_title("Cannot compress to stdout.")
+            .detail("Compression formats are detected by the output file extension, but stdout has none")
+            .hint("Pass the formats with the '--format' flag:")
+            .hint("  ouch compress <FILES>... - --format tar.gz");
+
+        return Err(error.into());
+    }
+    Ok(())
+}
+
 /// Check if compression is invalid because an archive format is necessary.
 ///
 /// Non-archive formats don't support multiple file compression or folder compression.
@@ -219,8 +374,10 @@ pub fn check_invalid_compression_with_non_archive_format(
         .detail(format!(
             "The compression format '{first_format}' does not accept multiple files.",
         ))
-        .detail("Formats that bundle files into an archive are tar and zip.")
-        .hint(format!("Try inserting 'tar.' or 'zip.' before '{first_format}'."))
+        .detail("Formats that bundle files into an archive are tar, zip and 7z (rar can only be decompressed).")
+        .hint(format!(
+            "Try inserting 'tar.', 'zip.' or '7z.' before '{first_format}'."
+        ))
         .hint(from_hint)
         .hint(to_hint);
 
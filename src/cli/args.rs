@@ -1,11 +1,11 @@
-use std::{ffi::OsString, path::PathBuf};
+use std::{ffi::OsString, num::NonZeroUsize, path::PathBuf};
 
 use clap::{Parser, ValueHint};
 
 // Ouch command line options (docstrings below are part of --help)
 /// A command-line utility for easily compressing and decompressing files and directories.
 ///
-/// Supported formats: tar, zip, gz, xz/lzma, bz/bz2, lz4, sz, zst.
+/// Supported formats: tar, zip, 7z, rar (decompression only), gz, xz/lzma, bz/bz2, lz4, sz, zst, br.
 ///
 /// Repository: https://github.com/ouch-org/ouch
 #[derive(Parser, Debug)]
@@ -41,6 +41,14 @@ pub struct CliArgs {
     #[arg(short, long, global = true)]
     pub format: Option<OsString>,
 
+    /// Number of threads to use, defaults to one per CPU
+    #[arg(short = 'j', long, value_name = "N", global = true)]
+    pub threads: Option<NonZeroUsize>,
+
+    /// Format specific option, like zstd.long=27 or xz.extreme=true, can be repeated
+    #[arg(long = "opt", value_name = "FORMAT.KEY=VALUE", global = true)]
+    pub options: Vec<String>,
+
     /// Ouch and claps subcommands
     #[command(subcommand)]
     pub cmd: Subcommand,
@@ -56,7 +64,8 @@ pub enum Subcommand {
         #[arg(required = true, num_args = 1..)]
         files: Vec<PathBuf>,
 
-        /// The resulting file. Its extensions can be used to specify the compression formats
+        /// The resulting file. Its extensions can be used to specify the compression formats,
+        /// use `-` to write to stdout (requires --format)
         #[arg(required = true, value_hint = ValueHint::FilePath)]
         output: PathBuf,
 
@@ -73,27 +82,195 @@ pub enum Subcommand {
         /// conflicts with --level and --fast
         #[arg(long, group = "compression-level")]
         slow: bool,
+
+        /// Archive the contents of symlinks and hardlinks, instead of the links themselves (tar, zip)
+        #[arg(long, visible_alias = "dereference")]
+        follow_symlinks: bool,
+
+        /// Create the same archive from the same files on any machine: sort entries, clamp modification
+        /// times, and drop owners and permissions other than the executable bit (tar, zip). Without
+        /// --source-date-epoch or SOURCE_DATE_EPOCH, every modification time is set to 0 (1970-01-01)
+        #[arg(long)]
+        reproducible: bool,
+
+        /// Unix timestamp that modification times are clamped to by --reproducible, taken from
+        /// SOURCE_DATE_EPOCH when not given
+        #[arg(long, value_name = "TIMESTAMP")]
+        source_date_epoch: Option<u64>,
+
+        /// Leave out files and directories matching the glob, like `target/` or `*.log`, can be repeated
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Only archive the files matching the glob, like `*.rs`, can be repeated, directories are still walked
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Leave out the files matching the globs listed in FILE, one per line, like a .gitignore
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        exclude_from: Vec<PathBuf>,
     },
     /// Decompresses one or more files, optionally into another folder
     #[command(visible_alias = "d")]
     Decompress {
-        /// Files to be decompressed
+        /// Files to be decompressed, use `-` to read from stdin
         #[arg(required = true, num_args = 1..)]
         files: Vec<PathBuf>,
 
         /// Place results in a directory other than the current one
         #[arg(short = 'd', long = "dir", value_hint = ValueHint::DirPath)]
         output_dir: Option<PathBuf>,
+
+        /// Only extract archive entries matching the given path or glob, can be repeated
+        #[arg(short = 'e', long = "entry", value_name = "PATTERN")]
+        entries: Vec<String>,
+
+        /// Abort if more than this many bytes are decompressed, accepts suffixes like 500M or 2G
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_size: Option<u64>,
+
+        /// Abort if more than this many archive entries are extracted
+        #[arg(long, value_name = "COUNT")]
+        max_entries: Option<u64>,
+
+        /// Abort if the data expands more than this many times its compressed size
+        #[arg(long, value_name = "RATIO")]
+        max_ratio: Option<u32>,
     },
     /// List contents of an archive
     #[command(visible_aliases = ["l", "ls"])]
     List {
-        /// Archives whose contents should be listed
+        /// Archives whose contents should be listed, use `-` to read from stdin
         #[arg(required = true, num_args = 1..)]
         archives: Vec<PathBuf>,
 
         /// Show archive contents as a tree
         #[arg(short, long)]
         tree: bool,
+
+        /// Show sizes, permissions, modification times and compression ratios of the entries
+        #[arg(short, long, conflicts_with = "tree")]
+        long: bool,
+
+        /// Print the entries as JSON, or as newline delimited JSON, to be read by other programs
+        #[arg(short, long, value_enum, default_value_t, conflicts_with_all = ["tree", "long"])]
+        output: ListOutput,
+    },
+    /// Test the integrity of one or more files, without extracting them
+    #[command(visible_alias = "t")]
+    Test {
+        /// Files to be tested, use `-` to read from stdin
+        #[arg(required = true, num_args = 1..)]
+        files: Vec<PathBuf>,
+    },
+    /// Convert a file to other formats, without extracting it to disk
+    Convert {
+        /// File to be converted, use `-` to read from stdin
+        #[arg(required = true)]
+        input: PathBuf,
+
+        /// The resulting file. Its extensions can be used to specify the compression formats,
+        /// use `-` to write to stdout (requires --format)
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        output: PathBuf,
+
+        /// Compression level, applied to all formats
+        #[arg(short, long, group = "compression-level")]
+        level: Option<i16>,
+
+        /// Fastest compression level possible,
+        /// conflicts with --level and --slow
+        #[arg(long, group = "compression-level")]
+        fast: bool,
+
+        /// Slowest (and best) compression level possible,
+        /// conflicts with --level and --fast
+        #[arg(long, group = "compression-level")]
+        slow: bool,
     },
+    /// Add files to an existing tar or zip archive, replacing the entries with the same paths
+    #[command(visible_alias = "a")]
+    Add {
+        /// The archive to add the files to
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        archive: PathBuf,
+
+        /// Files to be added
+        #[arg(required = true, num_args = 1..)]
+        files: Vec<PathBuf>,
+
+        /// Archive the contents of symlinks and hardlinks, instead of the links themselves
+        #[arg(long, visible_alias = "dereference")]
+        follow_symlinks: bool,
+    },
+    /// Like add, but only replaces the entries whose files were modified since they were archived
+    #[command(visible_alias = "u")]
+    Update {
+        /// The archive to update
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        archive: PathBuf,
+
+        /// Files to be added, or updated if they are newer than their entries
+        #[arg(required = true, num_args = 1..)]
+        files: Vec<PathBuf>,
+
+        /// Archive the contents of symlinks and hardlinks, instead of the links themselves
+        #[arg(long, visible_alias = "dereference")]
+        follow_symlinks: bool,
+    },
+    /// Remove entries from an existing tar or zip archive
+    #[command(visible_alias = "rm")]
+    Delete {
+        /// The archive to remove the entries from
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        archive: PathBuf,
+
+        /// Paths or globs of the entries to remove, directories are removed with their contents
+        #[arg(required = true, num_args = 1..)]
+        entries: Vec<String>,
+    },
+    /// Write a decompressed file, or one entry of an archive, to stdout
+    Cat {
+        /// File to be decompressed, use `-` to read from stdin
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Path of the entry to print, required for archives
+        entry: Option<PathBuf>,
+    },
+}
+
+/// How `ouch list` prints the contents of archives
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListOutput {
+    /// Human readable, one entry per line
+    #[default]
+    Text,
+    /// An array with one object per archive, with its entries inside
+    Json,
+    /// One object per line, each archive is followed by its entries
+    Ndjson,
+}
+
+/// Parse a number of bytes, with an optional binary suffix: K, M, G or T
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((i, suffix)) if suffix.is_ascii_alphabetic() => {
+            let multiplier = match suffix.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => return Err(format!("unknown size suffix '{suffix}', expected K, M, G or T")),
+            };
+            (&size[..i], multiplier)
+        }
+        _ => (size, 1),
+    };
+
+    let number: u64 = number.parse().map_err(|_| format!("invalid size '{size}'"))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{size}' is too large"))
 }
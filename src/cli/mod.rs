@@ -5,14 +5,20 @@ mod args;
 use std::{
     io,
     path::{Path, PathBuf},
+    slice,
     vec::Vec,
 };
 
 use clap::Parser;
 use fs_err as fs;
 
-pub use self::args::{CliArgs, Subcommand};
-use crate::{accessible::set_accessible, utils::FileVisibilityPolicy, QuestionPolicy};
+pub use self::args::{parse_size, CliArgs, ListOutput, Subcommand};
+use crate::{
+    accessible::set_accessible,
+    threads::set_threads,
+    utils::{is_stdio_path, set_format_options, FileVisibilityPolicy, FormatOptions},
+    QuestionPolicy,
+};
 
 impl CliArgs {
     /// A helper method that calls `clap::Parser::parse`.
@@ -24,11 +30,23 @@ impl CliArgs {
         let mut args = Self::parse();
 
         set_accessible(args.accessible);
+        set_threads(args.threads);
+        set_format_options(FormatOptions::new(&args.options)?);
 
-        let (Subcommand::Compress { files, .. }
-        | Subcommand::Decompress { files, .. }
-        | Subcommand::List { archives: files, .. }) = &mut args.cmd;
-        *files = canonicalize_files(files)?;
+        match &mut args.cmd {
+            Subcommand::Compress { files, .. } => *files = canonicalize_files(files)?,
+            Subcommand::Decompress { files, .. }
+            | Subcommand::List { archives: files, .. }
+            | Subcommand::Test { files } => *files = canonicalize_input_files(files)?,
+            Subcommand::Cat { file, .. } | Subcommand::Convert { input: file, .. } => {
+                *file = canonicalize_input_files(slice::from_ref(file))?.remove(0)
+            }
+            Subcommand::Add { archive, files, .. } | Subcommand::Update { archive, files, .. } => {
+                *archive = fs::canonicalize(&*archive)?;
+                *files = canonicalize_files(files)?;
+            }
+            Subcommand::Delete { archive, .. } => *archive = fs::canonicalize(&*archive)?,
+        }
 
         let skip_questions_positively = match (args.yes, args.no) {
             (false, false) => QuestionPolicy::Ask,
@@ -37,16 +55,57 @@ impl CliArgs {
             (true, true) => unreachable!(),
         };
 
-        let file_visibility_policy = FileVisibilityPolicy::new()
+        let mut file_visibility_policy = FileVisibilityPolicy::new()
             .read_git_exclude(args.gitignore)
             .read_ignore(args.gitignore)
             .read_git_ignore(args.gitignore)
             .read_hidden(args.hidden);
 
+        if let Subcommand::Compress {
+            exclude,
+            include,
+            exclude_from,
+            ..
+        } = &args.cmd
+        {
+            let mut exclude = exclude.clone();
+            for path in exclude_from {
+                exclude.extend(read_globs_file(path)?);
+            }
+            file_visibility_policy = file_visibility_policy.globs(include, &exclude)?;
+        }
+
         Ok((args, skip_questions_positively, file_visibility_policy))
     }
 }
 
+/// Read the globs of a file given to `--exclude-from`, skipping empty lines and `#` comments
+fn read_globs_file(path: &Path) -> io::Result<Vec<String>> {
+    let globs = fs::read_to_string(path)?
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+
+    Ok(globs)
+}
+
 fn canonicalize_files(files: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
     files.iter().map(fs::canonicalize).collect()
 }
+
+/// Like `canonicalize_files`, but `-` is kept as is, as it stands for stdin
+fn canonicalize_input_files(files: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
+    files
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            if is_stdio_path(path) {
+                Ok(path.to_path_buf())
+            } else {
+                fs::canonicalize(path)
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,167 @@
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use fs_err as fs;
+
+use crate::{
+    commands::{decompress::chain_reader_decoder, spill_zip_to_temp_file, warn_user_about_loading_sevenz_in_memory},
+    error::FinalError,
+    extension::{
+        split_first_compression_format,
+        CompressionFormat::{self, *},
+        Extension,
+    },
+    utils::{self, EscapedPathDisplay},
+    BUFFER_CAPACITY,
+};
+
+// Write a decompressed file, or one entry of an archive, to stdout
+//
+// File at input_file_path is opened for reading, example: "archive.tar.gz"
+// formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+// entry_path is the path of the entry inside of the archive, it must be given for archive formats only
+//
+// Nothing is asked to the user here, questions are printed to stdout and would end up mixed with the output
+pub fn cat_file(input_file_path: &Path, formats: Vec<Extension>, entry_path: Option<&Path>) -> crate::Result<()> {
+    let is_archive = formats.first().map(Extension::is_archive).unwrap_or(false);
+
+    let entry_path = match (is_archive, entry_path) {
+        (true, Some(entry_path)) => Some(entry_path),
+        (false, None) => None,
+        (true, None) => {
+            let error = FinalError::with_title(format!(
+                "Missing the entry to print from {}",
+                EscapedPathDisplay::new(input_file_path)
+            ))
+            .detail("Archives can contain many files, only one of them can be printed")
+            .hint("Pass the path of the entry after the archive, like:")
+            .hint(format!(
+                "  ouch cat {} path/to/file",
+                EscapedPathDisplay::new(input_file_path)
+            ))
+            .hint("Use `ouch list` to see the paths of the entries in the archive");
+
+            return Err(error.into());
+        }
+        (false, Some(_)) => {
+            let error = FinalError::with_title(format!(
+                "Cannot print an entry of {}",
+                EscapedPathDisplay::new(input_file_path)
+            ))
+            .detail("Only archives have entries, this file is printed as a whole")
+            .hint("Remove the entry path to print the decompressed file");
+
+            return Err(error.into());
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::with_capacity(BUFFER_CAPACITY, stdout.lock());
+
+    cat_into(input_file_path, &formats, entry_path, &mut writer)?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn cat_into(
+    input_file_path: &Path,
+    formats: &[Extension],
+    entry_path: Option<&Path>,
+    writer: &mut dyn Write,
+) -> crate::Result<()> {
+    // Input read from stdin can't be seeked, so it always goes through decoder chaining
+    let input_is_stdin = utils::is_stdio_path(input_file_path);
+
+    // Zip, 7z and RAR archives can be read directly when they are not wrapped by other formats,
+    // see `decompress_file`
+    match (input_is_stdin, formats, entry_path) {
+        (
+            false,
+            [Extension {
+                compression_formats: [Zip],
+                ..
+            }],
+            Some(entry_path),
+        ) => {
+            let zip_archive = zip::ZipArchive::new(fs::File::open(input_file_path)?)?;
+            return crate::archive::zip::cat_entry(zip_archive, entry_path, writer);
+        }
+        (
+            false,
+            [Extension {
+                compression_formats: [SevenZip],
+                ..
+            }],
+            Some(entry_path),
+        ) => return crate::archive::sevenz::cat_entry(fs::File::open(input_file_path)?, entry_path, writer),
+        (
+            false,
+            [Extension {
+                compression_formats: [Rar],
+                ..
+            }],
+            Some(entry_path),
+        ) => return crate::archive::rar::cat_entry(input_file_path, entry_path, writer),
+        _ => {}
+    }
+
+    let (first_extension, extensions) = split_first_compression_format(formats);
+    let mut reader = open_decoded(input_file_path, &extensions)?;
+
+    match (first_extension, entry_path) {
+        (Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd | Brotli, None) => {
+            reader = chain_reader_decoder(&first_extension, reader)?;
+            io::copy(&mut reader, writer)?;
+        }
+        (Tar, Some(entry_path)) => {
+            let reopen = || -> crate::Result<Box<dyn Read>> {
+                if input_is_stdin {
+                    let error = FinalError::with_title("Cannot print a hard link of an archive read from stdin")
+                        .detail("Its data is stored under the entry it links to, earlier in the archive")
+                        .hint("Use `ouch cat` with the path of the entry it links to instead");
+
+                    return Err(error.into());
+                }
+
+                Ok(open_decoded(input_file_path, &extensions)?)
+            };
+            crate::archive::tar::cat_entry(reader, entry_path, writer, &reopen)?;
+        }
+        (Zip, Some(entry_path)) => {
+            let zip_archive = zip::ZipArchive::new(spill_zip_to_temp_file(&mut reader, input_file_path)?)?;
+            crate::archive::zip::cat_entry(zip_archive, entry_path, writer)?;
+        }
+        (SevenZip, Some(entry_path)) => {
+            warn_user_about_loading_sevenz_in_memory();
+
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
+            crate::archive::sevenz::cat_entry(io::Cursor::new(vec), entry_path, writer)?;
+        }
+        (Rar, Some(entry_path)) => {
+            // The unrar library can only read archives from the file system, so the
+            // decompressed stream is spilled into a temporary file first
+            let mut temp_file = tempfile::NamedTempFile::new()?;
+            io::copy(&mut reader, &mut temp_file)?;
+            crate::archive::rar::cat_entry(temp_file.path(), entry_path, writer)?;
+        }
+        _ => unreachable!("the entry path is only given for archive formats, checked in `cat_file`"),
+    }
+
+    Ok(())
+}
+
+/// Open the file at `input_file_path`, or stdin, through the decoders of `formats`
+fn open_decoded(input_file_path: &Path, formats: &[CompressionFormat]) -> crate::Result<Box<dyn Read + Send>> {
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, utils::open_file_or_stdin(input_file_path)?);
+    let mut reader: Box<dyn Read + Send> = Box::new(reader);
+
+    for format in formats.iter().rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+
+    Ok(reader)
+}
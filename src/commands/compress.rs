@@ -7,9 +7,15 @@ use fs_err as fs;
 
 use crate::{
     archive,
-    commands::warn_user_about_loading_zip_in_memory,
-    extension::{split_first_compression_format, CompressionFormat::*, Extension},
-    utils::{user_wants_to_continue, FileVisibilityPolicy},
+    commands::warn_user_about_loading_sevenz_in_memory,
+    extension::{
+        split_first_compression_format,
+        CompressionFormat::{self, *},
+        Extension,
+    },
+    progress::Progress,
+    threads::threads,
+    utils::{format_options, is_stdio_path, user_wants_to_continue, FileVisibilityPolicy, Reproducible},
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
@@ -18,7 +24,10 @@ use crate::{
 /// # Arguments:
 /// - `files`: is the list of paths to be compressed: ["dir/file1.txt", "dir/file2.txt"]
 /// - `extensions`: is a list of compression formats for compressing, example: [Tar, Gz] (in compression order)
-/// - `output_file` is the resulting compressed file name, example: "archive.tar.gz"
+/// - `output_file`: is the file at `output_path` where the compressed data is written to, `None` for stdout
+/// - `output_path`: is the resulting compressed file name, example: "archive.tar.gz", or `-` for stdout
+/// - `follow_symlinks`: whether symlinks and hardlinks are archived as the files they point to, instead of links
+/// - `reproducible`: makes tar and zip archives only depend on the files, see `Reproducible`
 ///
 /// # Return value
 /// - Returns `Ok(true)` if compressed all files normally.
@@ -27,107 +36,277 @@ use crate::{
 pub fn compress_files(
     files: Vec<PathBuf>,
     extensions: Vec<Extension>,
-    output_file: fs::File,
+    output_file: Option<fs::File>,
     output_path: &Path,
     quiet: bool,
     question_policy: QuestionPolicy,
     file_visibility_policy: FileVisibilityPolicy,
+    follow_symlinks: bool,
+    reproducible: Option<Reproducible>,
     level: Option<i16>,
 ) -> crate::Result<bool> {
-    // If the input files contain a directory, then the total size will be underestimated
-    let file_writer = BufWriter::with_capacity(BUFFER_CAPACITY, output_file);
+    let (first_format, formats) = split_first_compression_format(&extensions);
 
-    let mut writer: Box<dyn Send + Write> = Box::new(file_writer);
+    let progress = Progress::new(
+        "Compressing",
+        || Some(total_size(&files, &file_visibility_policy)),
+        quiet,
+    );
 
-    // Grab previous encoder and wrap it inside of a new one
-    let chain_writer_encoder = |format: &_, encoder| -> crate::Result<_> {
-        let encoder: Box<dyn Send + Write> = match format {
-            Gzip => Box::new(
-                // by default, ParCompress uses a default compression level of 3
-                // instead of the regular default that flate2 uses
-                gzp::par::compress::ParCompress::<gzp::deflate::Gzip>::builder()
-                    .compression_level(
-                        level.map_or_else(Default::default, |l| gzp::Compression::new((l as u32).clamp(0, 9))),
-                    )
-                    .from_writer(encoder),
-            ),
-            Bzip => Box::new(bzip2::write::BzEncoder::new(
-                encoder,
-                level.map_or_else(Default::default, |l| bzip2::Compression::new((l as u32).clamp(1, 9))),
-            )),
-            Lz4 => Box::new(lzzzz::lz4f::WriteCompressor::new(
-                encoder,
-                lzzzz::lz4f::PreferencesBuilder::new()
-                    .compression_level(level.map_or(1, |l| (l as i32).clamp(1, lzzzz::lz4f::CLEVEL_MAX)))
-                    .build(),
-            )?),
-            Lzma => Box::new(xz2::write::XzEncoder::new(
-                encoder,
-                level.map_or(6, |l| (l as u32).clamp(0, 9)),
-            )),
-            Snappy => Box::new(
-                gzp::par::compress::ParCompress::<gzp::snap::Snap>::builder()
-                    .compression_level(gzp::par::compress::Compression::new(
-                        level.map_or_else(Default::default, |l| (l as u32).clamp(0, 9)),
-                    ))
-                    .from_writer(encoder),
-            ),
-            Zstd => {
-                let zstd_encoder = zstd::stream::write::Encoder::new(
-                    encoder,
-                    level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| {
-                        (l as i32).clamp(zstd::zstd_safe::min_c_level(), zstd::zstd_safe::max_c_level())
-                    }),
-                );
-                // Safety:
-                //     Encoder::new() can only fail if `level` is invalid, but the level
-                //     is `clamp`ed and therefore guaranteed to be valid
-                Box::new(zstd_encoder.unwrap().auto_finish())
-            }
-            Tar | Zip => unreachable!(),
-        };
-        Ok(encoder)
+    let output_file: Box<dyn Write + Send> = match output_file {
+        // Zip archives require io::Seek, which the output file provides when nothing is written after them
+        Some(file) if first_format == Zip && formats.is_empty() => {
+            archive::zip::build_archive_from_paths(
+                &files,
+                output_path,
+                BufWriter::with_capacity(BUFFER_CAPACITY, file),
+                file_visibility_policy,
+                follow_symlinks,
+                reproducible,
+                &progress,
+                quiet,
+            )?
+            .flush()?;
+
+            return Ok(true);
+        }
+        Some(file) => Box::new(file),
+        None => Box::new(io::stdout()),
     };
 
-    let (first_format, formats) = split_first_compression_format(&extensions);
+    let file_writer = BufWriter::with_capacity(BUFFER_CAPACITY, output_file);
+
+    let mut writer: Box<dyn Send + Write> = Box::new(file_writer);
 
     for format in formats.iter().rev() {
-        writer = chain_writer_encoder(format, writer)?;
+        writer = chain_writer_encoder(format, writer, level)?;
     }
 
     match first_format {
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
-            writer = chain_writer_encoder(&first_format, writer)?;
-            let mut reader = fs::File::open(&files[0]).unwrap();
+        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd | Brotli => {
+            writer = chain_writer_encoder(&first_format, writer, level)?;
+            let mut reader = progress.reader(fs::File::open(&files[0]).unwrap());
 
             io::copy(&mut reader, &mut writer)?;
         }
         Tar => {
-            archive::tar::build_archive_from_paths(&files, output_path, &mut writer, file_visibility_policy, quiet)?;
+            archive::tar::build_archive_from_paths(
+                &files,
+                output_path,
+                &mut writer,
+                file_visibility_policy,
+                follow_symlinks,
+                reproducible,
+                &progress,
+                quiet,
+            )?;
             writer.flush()?;
         }
         Zip => {
+            // The output can't be seeked through the encoders or on stdout, so the archive is built in
+            // a temporary file first and copied to it once complete
+            let mut temp_file = archive::zip::build_archive_from_paths(
+                &files,
+                output_path,
+                BufWriter::with_capacity(BUFFER_CAPACITY, zip_temp_file(output_path)?),
+                file_visibility_policy,
+                follow_symlinks,
+                reproducible,
+                &progress,
+                quiet,
+            )?
+            .into_inner()
+            .map_err(|err| err.into_error())?;
+            temp_file.rewind()?;
+            io::copy(&mut temp_file, &mut writer)?;
+            writer.flush()?;
+        }
+        SevenZip => {
             if !formats.is_empty() {
-                warn_user_about_loading_zip_in_memory();
+                warn_user_about_loading_sevenz_in_memory();
 
-                if !user_wants_to_continue(output_path, question_policy, QuestionAction::Compression)? {
+                // Questions are printed to stdout, which would mix them with the compressed data
+                if !is_stdio_path(output_path)
+                    && !user_wants_to_continue(output_path, question_policy, QuestionAction::Compression)?
+                {
                     return Ok(false);
                 }
             }
 
             let mut vec_buffer = Cursor::new(vec![]);
 
-            archive::zip::build_archive_from_paths(
+            archive::sevenz::build_archive_from_paths(
                 &files,
                 output_path,
                 &mut vec_buffer,
                 file_visibility_policy,
+                &progress,
                 quiet,
             )?;
             vec_buffer.rewind()?;
             io::copy(&mut vec_buffer, &mut writer)?;
         }
+        Rar => unreachable!("RAR compression is rejected by `check::check_formats_support_compression`"),
     }
 
     Ok(true)
 }
+
+/// Anonymous temporary file to build a zip archive in, deleted once closed, see `compress_files`
+///
+/// It's created next to `output_path`, where there should be room for the archive, or in the
+/// system's temporary directory when writing to stdout.
+pub(super) fn zip_temp_file(output_path: &Path) -> crate::Result<std::fs::File> {
+    let temp_file = match output_path.parent() {
+        _ if is_stdio_path(output_path) => tempfile::tempfile()?,
+        Some(parent) if !parent.as_os_str().is_empty() => tempfile::tempfile_in(parent)?,
+        _ => tempfile::tempfile_in(".")?,
+    };
+
+    Ok(temp_file)
+}
+
+/// Sum of the sizes of the files that compressing `files` reads, used to tell how far along it is
+fn total_size(files: &[PathBuf], file_visibility_policy: &FileVisibilityPolicy) -> u64 {
+    files
+        .iter()
+        .flat_map(|file| file_visibility_policy.build_walker(file))
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// liblzma's `LZMA_PRESET_EXTREME`, which xz2 doesn't export
+const XZ_PRESET_EXTREME: u32 = 1 << 31;
+
+/// How much memory the xz encoder can use before it's given fewer threads
+const XZ_MEMORY_LIMIT: u64 = 1 << 30;
+
+/// Grab previous encoder and wrap it inside of a new one
+///
+/// Format specific settings are taken from `--opt`, see `FormatOptions`
+pub(super) fn chain_writer_encoder(
+    format: &CompressionFormat,
+    encoder: Box<dyn Write + Send>,
+    level: Option<i16>,
+) -> crate::Result<Box<dyn Write + Send>> {
+    let threads = threads();
+    let options = format_options();
+
+    let encoder: Box<dyn Send + Write> = match format {
+        Gzip => Box::new(
+            // by default, ParCompress uses a default compression level of 3
+            // instead of the regular default that flate2 uses
+            //
+            // the gzip header written by ParCompress has no timestamp nor file name,
+            // which `--reproducible` relies on
+            gzp::par::compress::ParCompress::<gzp::deflate::Gzip>::builder()
+                .compression_level(
+                    level.map_or_else(Default::default, |l| gzp::Compression::new((l as u32).clamp(0, 9))),
+                )
+                .num_threads(threads)
+                // Safe unwrap, only 0 threads are rejected
+                .unwrap()
+                .from_writer(encoder),
+        ),
+        Bzip => Box::new(bzip2::write::BzEncoder::new(
+            encoder,
+            level.map_or_else(Default::default, |l| bzip2::Compression::new((l as u32).clamp(1, 9))),
+        )),
+        Lz4 => {
+            use lzzzz::lz4f::BlockSize;
+
+            let block_size = match options.lz4_block_size {
+                Some(size) if size == 64 << 10 => BlockSize::Max64KB,
+                Some(size) if size == 256 << 10 => BlockSize::Max256KB,
+                Some(size) if size == 1 << 20 => BlockSize::Max1MB,
+                Some(size) if size == 4 << 20 => BlockSize::Max4MB,
+                _ => BlockSize::Default,
+            };
+            Box::new(lzzzz::lz4f::WriteCompressor::new(
+                encoder,
+                lzzzz::lz4f::PreferencesBuilder::new()
+                    .compression_level(level.map_or(1, |l| (l as i32).clamp(1, lzzzz::lz4f::CLEVEL_MAX)))
+                    .block_size(block_size)
+                    .build(),
+            )?)
+        }
+        Lzma => {
+            let level = level.map_or(6, |l| (l as u32).clamp(0, 9));
+            let preset = if options.xz_extreme {
+                level | XZ_PRESET_EXTREME
+            } else {
+                level
+            };
+            let mut lzma_options = xz2::stream::LzmaOptions::new_preset(preset).map_err(io::Error::from)?;
+            if let Some(dict_size) = options.xz_dict_size {
+                lzma_options.dict_size(dict_size);
+            }
+            let mut filters = xz2::stream::Filters::new();
+            filters.lzma2(&lzma_options);
+
+            // The input is always split in blocks, compressed by different threads, so that the output doesn't
+            // depend on how many there are
+            let mut builder = xz2::stream::MtStreamBuilder::new();
+            builder
+                .filters(filters)
+                // 0 picks the default, 3 times the dictionary size
+                .block_size(options.xz_block_size.unwrap_or(0))
+                .check(xz2::stream::Check::Crc64);
+            // Each thread buffers a block and has its own encoder, with the higher presets and many CPUs this
+            // quickly adds up to gigabytes
+            let mut threads = threads as u32;
+            while threads > 1 && builder.threads(threads).memusage() > XZ_MEMORY_LIMIT {
+                threads -= 1;
+            }
+            let stream = builder.threads(threads).encoder();
+            Box::new(xz2::write::XzEncoder::new_stream(
+                encoder,
+                stream.map_err(io::Error::from)?,
+            ))
+        }
+        Snappy => Box::new(
+            gzp::par::compress::ParCompress::<gzp::snap::Snap>::builder()
+                .compression_level(gzp::par::compress::Compression::new(
+                    level.map_or_else(Default::default, |l| (l as u32).clamp(0, 9)),
+                ))
+                .num_threads(threads)
+                // Safe unwrap, only 0 threads are rejected
+                .unwrap()
+                .from_writer(encoder),
+        ),
+        Zstd => {
+            let level = options.zstd_level.unwrap_or_else(|| {
+                level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| {
+                    (l as i32).clamp(zstd::zstd_safe::min_c_level(), zstd::zstd_safe::max_c_level())
+                })
+            });
+            // The level is always valid, `clamp`ed or checked by `FormatOptions`, but the dictionary may not be
+            let mut zstd_encoder = match &options.zstd_dict {
+                Some(dictionary) => zstd::stream::write::Encoder::with_dictionary(encoder, level, dictionary)?,
+                None => zstd::stream::write::Encoder::new(encoder, level)?,
+            };
+            if let Some(window_log) = options.zstd_long {
+                zstd_encoder.long_distance_matching(true)?;
+                zstd_encoder.window_log(window_log)?;
+            }
+            // Unlike the single threaded mode, the output of the workers doesn't depend on how many there are
+            zstd_encoder.multithread(threads as u32)?;
+            Box::new(zstd_encoder.auto_finish())
+        }
+        Brotli => {
+            let default_level = 11;
+            let level = level.map_or(default_level, |l| (l as u32).clamp(0, 11));
+            let default_window_size = 22;
+            Box::new(brotli::enc::writer::CompressorWriter::new(
+                encoder,
+                BUFFER_CAPACITY,
+                level,
+                options.brotli_window.unwrap_or(default_window_size),
+            ))
+        }
+        Tar | Zip | SevenZip | Rar => unreachable!(),
+    };
+    Ok(encoder)
+}
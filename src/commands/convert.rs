@@ -0,0 +1,130 @@
+use std::{
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    path::Path,
+};
+
+use fs_err as fs;
+
+use crate::{
+    commands::{
+        compress::{chain_writer_encoder, zip_temp_file},
+        decompress::chain_reader_decoder,
+        spill_zip_to_temp_file,
+    },
+    error::FinalError,
+    extension::{split_first_compression_format, CompressionFormat::*, Extension},
+    utils::{self, EscapedPathDisplay},
+    BUFFER_CAPACITY,
+};
+
+// Convert a file from one set of formats to another, without extracting it to disk
+//
+// File at input_file_path is opened for reading, example: "release.tar.gz"
+// input_formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+// output_formats contains each format used for compression, example: [Tar, Zstd] (in compression order)
+// output_file is where the converted file is written to, the file at output_path or stdout
+//
+// When both sides start with the same archive format (or both are single file formats), the decoder
+// chain of the input is piped straight into the encoder chain of the output, otherwise tar and zip
+// archives are converted entry by entry
+pub fn convert_file(
+    input_file_path: &Path,
+    input_formats: Vec<Extension>,
+    output_file: Box<dyn Write + Send>,
+    output_path: &Path,
+    output_formats: Vec<Extension>,
+    quiet: bool,
+    level: Option<i16>,
+) -> crate::Result<()> {
+    let (input_first_format, input_formats_rest) = split_first_compression_format(&input_formats);
+    let (output_first_format, output_formats_rest) = split_first_compression_format(&output_formats);
+
+    let input_is_archive = input_formats[0].is_archive();
+    let output_is_archive = output_formats[0].is_archive();
+
+    let pipes_directly = input_first_format == output_first_format || !input_is_archive && !output_is_archive;
+    let converts_entries = matches!((input_first_format, output_first_format), (Tar, Zip) | (Zip, Tar));
+
+    if !pipes_directly && !converts_entries {
+        let error = FinalError::with_title(format!(
+            "Cannot convert '{}' to '{}'.",
+            EscapedPathDisplay::new(input_file_path),
+            EscapedPathDisplay::new(output_path)
+        ))
+        .detail(format!(
+            "Converting from '{}' to '{}' is not supported.",
+            input_formats[0], output_formats[0]
+        ))
+        .detail("Archives can only be converted between tar and zip, and single file formats between each other.")
+        .hint("Decompress the file and compress the result instead.");
+
+        return Err(error.into());
+    }
+
+    // Input read from stdin can't be seeked, so it always goes through decoder chaining
+    let input_is_stdin = utils::is_stdio_path(input_file_path);
+
+    let mut writer: Box<dyn Write + Send> = Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, output_file));
+
+    for format in output_formats_rest.iter().rev() {
+        writer = chain_writer_encoder(format, writer, level)?;
+    }
+
+    // Zip archives can be read directly when they are not wrapped by other formats, see `decompress_file`
+    if let (false, false, Zip, []) = (
+        input_is_stdin,
+        pipes_directly,
+        input_first_format,
+        input_formats_rest.as_slice(),
+    ) {
+        let zip_archive = zip::ZipArchive::new(fs::File::open(input_file_path)?)?;
+        crate::archive::tar::build_archive_from_zip(zip_archive, &mut writer, quiet)?;
+        writer.flush()?;
+
+        return Ok(());
+    }
+
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, utils::open_file_or_stdin(input_file_path)?);
+    let mut reader: Box<dyn Read + Send> = Box::new(reader);
+
+    for format in input_formats_rest.iter().rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+
+    if pipes_directly {
+        // The single file formats are the last decoder and the first encoder of each chain,
+        // archives are left as they are
+        if !input_is_archive {
+            reader = chain_reader_decoder(&input_first_format, reader)?;
+            writer = chain_writer_encoder(&output_first_format, writer, level)?;
+        }
+
+        io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+
+        return Ok(());
+    }
+
+    match (input_first_format, output_first_format) {
+        (Tar, Zip) => {
+            // Zip archives require io::Seek, so they are built in a temporary file, like in `compress_files`
+            let temp_file = BufWriter::with_capacity(BUFFER_CAPACITY, zip_temp_file(output_path)?);
+            let mut temp_file =
+                crate::archive::zip::build_archive_from_tar(tar::Archive::new(reader), temp_file, quiet)?
+                    .into_inner()
+                    .map_err(|err| err.into_error())?;
+            temp_file.rewind()?;
+            io::copy(&mut temp_file, &mut writer)?;
+            writer.flush()?;
+        }
+        (Zip, Tar) => {
+            let zip_archive = zip::ZipArchive::new(spill_zip_to_temp_file(&mut reader, input_file_path)?)?;
+
+            crate::archive::tar::build_archive_from_zip(zip_archive, &mut writer, quiet)?;
+            writer.flush()?;
+        }
+        _ => unreachable!("checked by `converts_entries`"),
+    }
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use std::{
-    io::{self, BufReader, Read},
+    io::{self, BufRead, BufReader, Read},
     ops::ControlFlow,
     path::{Path, PathBuf},
 };
@@ -7,14 +7,20 @@ use std::{
 use fs_err as fs;
 
 use crate::{
-    commands::warn_user_about_loading_zip_in_memory,
+    commands::warn_user_about_loading_sevenz_in_memory,
+    error::FinalError,
     extension::{
         split_first_compression_format,
         CompressionFormat::{self, *},
         Extension,
     },
     info,
-    utils::{self, nice_directory_display, user_wants_to_continue},
+    progress::Progress,
+    threads::threads,
+    utils::{
+        self, format_options, nice_directory_display, user_wants_to_continue, DecompressionLimits, EntryFilter,
+        LimitTracker,
+    },
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
@@ -24,34 +30,51 @@ use crate::{
 // formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
 // output_dir it's where the file will be decompressed to, this function assumes that the directory exists
 // output_file_path is only used when extracting single file formats, not archive formats like .tar or .zip
+// entry_filter selects which archive entries are extracted, it's always empty for single file formats
+// limits abort the decompression of tar, zip and single file formats, they are rejected for 7z and rar
+// by `check::check_limits_support_formats`
+// progress counts the bytes read from input_file_path, it can be shared with other files decompressed in parallel
+#[allow(clippy::too_many_arguments)]
 pub fn decompress_file(
     input_file_path: &Path,
     formats: Vec<Extension>,
     output_dir: &Path,
     output_file_path: PathBuf,
+    entry_filter: &EntryFilter,
+    limits: DecompressionLimits,
     question_policy: QuestionPolicy,
+    progress: &Progress,
     quiet: bool,
 ) -> crate::Result<()> {
     assert!(output_dir.exists());
-    let reader = fs::File::open(input_file_path)?;
+
+    let limited = !limits.is_unlimited();
+    let limits = LimitTracker::new(limits);
+
+    // Input read from stdin can't be seeked, so it always goes through decoder chaining
+    let input_is_stdin = utils::is_stdio_path(input_file_path);
 
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
     //
-    // This is the only case where we can read and unpack it directly, without having to do
-    // in-memory decompression/copying first.
-    //
-    // Any other Zip decompression done can take up the whole RAM and freeze ouch.
-    if let [Extension {
-        compression_formats: [Zip],
-        ..
-    }] = formats.as_slice()
+    // This is the only case where we can read and unpack it directly, instead of reading its
+    // entries one after the other, see `unpack_archive_stream`.
+    if let (
+        false,
+        [Extension {
+            compression_formats: [Zip],
+            ..
+        }],
+    ) = (input_is_stdin, formats.as_slice())
     {
-        let zip_archive = zip::ZipArchive::new(reader)?;
+        // Each thread extracting from the archive opens it again
+        let open_archive =
+            || -> crate::Result<_> { Ok(zip::ZipArchive::new(progress.reader(fs::File::open(input_file_path)?))?) };
         let files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
-            |output_dir| crate::archive::zip::unpack_archive(zip_archive, output_dir, quiet),
+            |output_dir| crate::archive::zip::unpack_archive(open_archive, output_dir, entry_filter, &limits, quiet),
             output_dir,
             &output_file_path,
+            entry_filter,
             question_policy,
         )? {
             files
@@ -73,48 +96,112 @@ pub fn decompress_file(
         return Ok(());
     }
 
-    // Will be used in decoder chaining
-    let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
-    let mut reader: Box<dyn Read> = Box::new(reader);
-
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder = |format: &CompressionFormat, decoder: Box<dyn Read>| -> crate::Result<Box<dyn Read>> {
-        let decoder: Box<dyn Read> = match format {
-            Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-            Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-            Lz4 => Box::new(lzzzz::lz4f::ReadDecompressor::new(decoder)?),
-            Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-            Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
-            Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-            Tar | Zip => unreachable!(),
+    // 7z archives also require io::Seek, so the same applies to them
+    if let (
+        false,
+        [Extension {
+            compression_formats: [SevenZip],
+            ..
+        }],
+    ) = (input_is_stdin, formats.as_slice())
+    {
+        let reader = progress.reader(fs::File::open(input_file_path)?);
+        let files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
+            |output_dir| crate::archive::sevenz::unpack_archive(reader, output_dir, entry_filter, quiet),
+            output_dir,
+            &output_file_path,
+            entry_filter,
+            question_policy,
+        )? {
+            files
+        } else {
+            return Ok(());
         };
-        Ok(decoder)
-    };
+
+        // this is only printed once, so it doesn't result in much text. On the other hand,
+        // having a final status message is important especially in an accessibility context
+        // as screen readers may not read a commands exit code, making it hard to reason
+        // about whether the command succeeded without such a message
+        info!(
+            accessible,
+            "Successfully decompressed archive in {} ({} files).",
+            nice_directory_display(output_dir),
+            files_unpacked
+        );
+
+        return Ok(());
+    }
+
+    // RAR archives are read straight from their path by the unrar library
+    if let (
+        false,
+        [Extension {
+            compression_formats: [Rar],
+            ..
+        }],
+    ) = (input_is_stdin, formats.as_slice())
+    {
+        let files_unpacked = if let ControlFlow::Continue(files) = smart_unpack(
+            |output_dir| crate::archive::rar::unpack_archive(input_file_path, output_dir, entry_filter, quiet),
+            output_dir,
+            &output_file_path,
+            entry_filter,
+            question_policy,
+        )? {
+            files
+        } else {
+            return Ok(());
+        };
+
+        // this is only printed once, so it doesn't result in much text. On the other hand,
+        // having a final status message is important especially in an accessibility context
+        // as screen readers may not read a commands exit code, making it hard to reason
+        // about whether the command succeeded without such a message
+        info!(
+            accessible,
+            "Successfully decompressed archive in {} ({} files).",
+            nice_directory_display(output_dir),
+            files_unpacked
+        );
+
+        return Ok(());
+    }
+
+    // Will be used in decoder chaining
+    let reader = utils::open_file_or_stdin(input_file_path)?;
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, progress.reader(limits.count_input(reader)));
+    let mut reader: Box<dyn Read + Send> = Box::new(reader);
 
     let (first_extension, extensions) = split_first_compression_format(&formats);
 
     for format in extensions.iter().rev() {
-        reader = chain_reader_decoder(format, reader)?;
+        reader = chain_limited_reader_decoder(format, reader, limited)?;
     }
 
     let files_unpacked = match first_extension {
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
-            reader = chain_reader_decoder(&first_extension, reader)?;
+        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd | Brotli => {
+            reader = chain_limited_reader_decoder(&first_extension, reader, limited)?;
 
             let mut writer = match utils::ask_to_create_file(&output_file_path, question_policy)? {
                 Some(file) => file,
                 None => return Ok(()),
             };
 
-            io::copy(&mut reader, &mut writer)?;
+            if let Err(err) = io::copy(&mut limits.limit_output(reader), &mut writer) {
+                // Don't leave a truncated file behind, it may also be huge if a limit was exceeded
+                drop(writer);
+                let _ = fs::remove_file(&output_file_path);
+                return Err(err.into());
+            }
 
             1
         }
         Tar => {
             if let ControlFlow::Continue(files) = smart_unpack(
-                |output_dir| crate::archive::tar::unpack_archive(reader, output_dir, quiet),
+                |output_dir| crate::archive::tar::unpack_archive(reader, output_dir, entry_filter, &limits, quiet),
                 output_dir,
                 &output_file_path,
+                entry_filter,
                 question_policy,
             )? {
                 files
@@ -123,22 +210,71 @@ pub fn decompress_file(
             }
         }
         Zip => {
+            // Only written to disk when some entries can't be read from the stream, next to the output,
+            // where there should be room for the archive's contents anyway
+            let spill_dir = output_dir;
+
+            if let ControlFlow::Continue(files) = smart_unpack(
+                |output_dir| {
+                    crate::archive::zip::unpack_archive_stream(
+                        reader,
+                        output_dir,
+                        spill_dir,
+                        entry_filter,
+                        &limits,
+                        quiet,
+                    )
+                },
+                output_dir,
+                &output_file_path,
+                entry_filter,
+                question_policy,
+            )? {
+                files
+            } else {
+                return Ok(());
+            }
+        }
+        SevenZip => {
             if formats.len() > 1 {
-                warn_user_about_loading_zip_in_memory();
+                warn_user_about_loading_sevenz_in_memory();
 
-                if !user_wants_to_continue(input_file_path, question_policy, QuestionAction::Decompression)? {
+                // Questions are answered through stdin, which is already taken by the archive
+                if !input_is_stdin
+                    && !user_wants_to_continue(input_file_path, question_policy, QuestionAction::Decompression)?
+                {
                     return Ok(());
                 }
             }
 
             let mut vec = vec![];
             io::copy(&mut reader, &mut vec)?;
-            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
 
             if let ControlFlow::Continue(files) = smart_unpack(
-                |output_dir| crate::archive::zip::unpack_archive(zip_archive, output_dir, quiet),
+                |output_dir| {
+                    crate::archive::sevenz::unpack_archive(io::Cursor::new(vec), output_dir, entry_filter, quiet)
+                },
                 output_dir,
                 &output_file_path,
+                entry_filter,
+                question_policy,
+            )? {
+                files
+            } else {
+                return Ok(());
+            }
+        }
+        Rar => {
+            // The unrar library can only read archives from the file system, so the
+            // decompressed stream is spilled into a temporary file first
+            let mut temp_file = tempfile::NamedTempFile::new()?;
+            io::copy(&mut reader, &mut temp_file)?;
+
+            if let ControlFlow::Continue(files) = smart_unpack(
+                |output_dir| crate::archive::rar::unpack_archive(temp_file.path(), output_dir, entry_filter, quiet),
+                output_dir,
+                &output_file_path,
+                entry_filter,
                 question_policy,
             )? {
                 files
@@ -162,15 +298,90 @@ pub fn decompress_file(
     Ok(())
 }
 
+/// Grab previous decoder and wrap it inside of a new one
+///
+/// Multi decoders are used so that files made of concatenated members/streams
+/// (e.g. `cat a.gz b.gz > c.gz`, or the output of pbzip2 and pixz) are fully decompressed
+pub(super) fn chain_reader_decoder(
+    format: &CompressionFormat,
+    decoder: Box<dyn Read + Send>,
+) -> crate::Result<Box<dyn Read + Send>> {
+    chain_limited_reader_decoder(format, decoder, false)
+}
+
+/// Like `chain_reader_decoder`, for data checked against decompression limits when `limited`
+///
+/// The parallel gzip decoder allocates a buffer of the size written at the end of each block before
+/// inflating it, up to 4 GiB, so limited gzip files are always inflated by one thread.
+fn chain_limited_reader_decoder(
+    format: &CompressionFormat,
+    decoder: Box<dyn Read + Send>,
+    limited: bool,
+) -> crate::Result<Box<dyn Read + Send>> {
+    let decoder: Box<dyn Read + Send> = match format {
+        Gzip => {
+            use gzp::{deflate::Bgzf, par::decompress::ParDecompressBuilder};
+
+            let threads = threads();
+            let mut decoder = BufReader::with_capacity(BUFFER_CAPACITY, decoder);
+
+            // BGZF files are made of blocks of at most 64 KiB, whose sizes are in their headers, so they're
+            // split and inflated by different threads. Other gzip files, like mgzip ones whose blocks can be
+            // of any size, are inflated in order
+            match gzip_block_subfield(decoder.fill_buf()?) {
+                Some([b'B', b'C']) if threads > 1 && !limited => Box::new(
+                    ParDecompressBuilder::<Bgzf>::new()
+                        .num_threads(threads)
+                        // Safe unwrap, only 0 threads are rejected
+                        .unwrap()
+                        .from_reader(decoder),
+                ),
+                _ => Box::new(flate2::read::MultiGzDecoder::new(decoder)),
+            }
+        }
+        Bzip => Box::new(bzip2::read::MultiBzDecoder::new(decoder)),
+        Lz4 => Box::new(lzzzz::lz4f::ReadDecompressor::new(decoder)?),
+        Lzma => Box::new(xz2::read::XzDecoder::new_multi_decoder(decoder)),
+        Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
+        Zstd => {
+            let options = format_options();
+            let mut zstd_decoder = match &options.zstd_dict {
+                Some(dictionary) => zstd::stream::Decoder::with_dictionary(BufReader::new(decoder), dictionary)?,
+                None => zstd::stream::Decoder::new(decoder)?,
+            };
+            // Frames with windows larger than 2^27 bytes are rejected unless allowed
+            if let Some(window_log) = options.zstd_long {
+                zstd_decoder.window_log_max(window_log)?;
+            }
+            Box::new(zstd_decoder)
+        }
+        Brotli => Box::new(brotli::Decompressor::new(decoder, BUFFER_CAPACITY)),
+        Tar | Zip | SevenZip | Rar => unreachable!(),
+    };
+    Ok(decoder)
+}
+
+/// Identifier of the first extra subfield of a gzip header, which tells gzip files made of
+/// independently compressed blocks apart: `BC` for BGZF (written by bgzip) and `IG` for mgzip
+fn gzip_block_subfield(header: &[u8]) -> Option<[u8; 2]> {
+    match header {
+        // ID1, ID2, CM (deflate) and FLG (only FEXTRA), then MTIME, XFL, OS and XLEN
+        [0x1f, 0x8b, 8, 4, _, _, _, _, _, _, _, _, si1, si2, ..] => Some([*si1, *si2]),
+        _ => None,
+    }
+}
+
 /// Unpacks an archive with some heuristics
 /// - If the archive contains only one file, it will be extracted to the `output_dir`
 /// - If the archive contains multiple files, it will be extracted to a subdirectory of the
 ///   output_dir named after the archive (given by `output_file_path`)
+/// - If `entry_filter` has patterns but no entry was selected by them, nothing is moved and an error is returned
 /// Note: This functions assumes that `output_dir` exists
 fn smart_unpack(
     unpack_fn: impl FnOnce(&Path) -> crate::Result<usize>,
     output_dir: &Path,
     output_file_path: &Path,
+    entry_filter: &EntryFilter,
     question_policy: QuestionPolicy,
 ) -> crate::Result<ControlFlow<(), usize>> {
     assert!(output_dir.exists());
@@ -182,7 +393,22 @@ fn smart_unpack(
         nice_directory_display(temp_dir_path)
     );
 
-    let files = unpack_fn(temp_dir_path)?;
+    let files = match unpack_fn(temp_dir_path) {
+        Ok(files) => files,
+        Err(err) => {
+            // Remove what was extracted so far, which can fill the disk when a decompression limit was exceeded
+            let _ = temp_dir.close();
+            return Err(err);
+        }
+    };
+
+    if files == 0 && !entry_filter.is_empty() {
+        let error = FinalError::with_title("No entries matched the given patterns")
+            .detail("Nothing was extracted")
+            .hint("Use `ouch list` to see the paths of the entries in the archive");
+
+        return Err(error.into());
+    }
 
     let root_contains_only_one_element = fs::read_dir(temp_dir_path)?.count() == 1;
     if root_contains_only_one_element {
@@ -6,10 +6,11 @@ use std::{
 use fs_err as fs;
 
 use crate::{
-    commands::warn_user_about_loading_zip_in_memory,
+    cli::ListOutput,
+    commands::{decompress::chain_reader_decoder, warn_user_about_loading_sevenz_in_memory, zip_spill_dir},
     extension::CompressionFormat::{self, *},
     list::{self, FileInArchive, ListOptions},
-    utils::user_wants_to_continue,
+    utils::{self, user_wants_to_continue},
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
@@ -21,42 +22,46 @@ pub fn list_archive_contents(
     list_options: ListOptions,
     question_policy: QuestionPolicy,
 ) -> crate::Result<()> {
-    let reader = fs::File::open(archive_path)?;
+    // Input read from stdin can't be seeked, so it always goes through decoder chaining
+    let input_is_stdin = utils::is_stdio_path(archive_path);
+
+    // Questions are answered through stdin, which may already be taken by the archive, and
+    // printed to stdout, where they would break the JSON output
+    let can_ask_questions = !input_is_stdin && list_options.output == ListOutput::Text;
 
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
     //
-    // This is the only case where we can read and unpack it directly, without having to do
-    // in-memory decompression/copying first.
-    //
-    // Any other Zip decompression done can take up the whole RAM and freeze ouch.
-    if let &[Zip] = formats.as_slice() {
-        let zip_archive = zip::ZipArchive::new(reader)?;
+    // This is the only case where we can read it directly, otherwise it's read entry by entry from
+    // the decoders, see `archive::zip::list_archive_stream`.
+    if let (false, &[Zip]) = (input_is_stdin, formats.as_slice()) {
+        let zip_archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
         let files = crate::archive::zip::list_archive(zip_archive);
-        list::list_files(archive_path, files, list_options)?;
+        list::list_files(archive_path, &formats, files, list_options)?;
+
+        return Ok(());
+    }
+
+    // 7z archives also require io::Seek, so the same applies to them
+    if let (false, &[SevenZip]) = (input_is_stdin, formats.as_slice()) {
+        let files = crate::archive::sevenz::list_archive(fs::File::open(archive_path)?)?;
+        list::list_files(archive_path, &formats, files, list_options)?;
+
+        return Ok(());
+    }
+
+    // RAR archives are read straight from their path by the unrar library
+    if let (false, &[Rar]) = (input_is_stdin, formats.as_slice()) {
+        let files = crate::archive::rar::list_archive(archive_path)?;
+        list::list_files(archive_path, &formats, files, list_options)?;
 
         return Ok(());
     }
 
     // Will be used in decoder chaining
-    let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, utils::open_file_or_stdin(archive_path)?);
     let mut reader: Box<dyn Read + Send> = Box::new(reader);
 
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder =
-        |format: &CompressionFormat, decoder: Box<dyn Read + Send>| -> crate::Result<Box<dyn Read + Send>> {
-            let decoder: Box<dyn Read + Send> = match format {
-                Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-                Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-                Lz4 => Box::new(lzzzz::lz4f::ReadDecompressor::new(decoder)?),
-                Lzma => Box::new(xz2::read::XzDecoder::new(decoder)),
-                Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
-                Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-                Tar | Zip => unreachable!(),
-            };
-            Ok(decoder)
-        };
-
     for format in formats.iter().skip(1).rev() {
         reader = chain_reader_decoder(format, reader)?;
     }
@@ -64,24 +69,40 @@ pub fn list_archive_contents(
     let files: Box<dyn Iterator<Item = crate::Result<FileInArchive>>> = match formats[0] {
         Tar => Box::new(crate::archive::tar::list_archive(tar::Archive::new(reader))),
         Zip => {
+            let files = crate::archive::zip::list_archive_stream(reader, zip_spill_dir(archive_path))?;
+
+            Box::new(files.into_iter().map(Ok))
+        }
+        SevenZip => {
             if formats.len() > 1 {
-                warn_user_about_loading_zip_in_memory();
+                warn_user_about_loading_sevenz_in_memory();
 
-                if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
+                if can_ask_questions
+                    && !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)?
+                {
                     return Ok(());
                 }
             }
 
             let mut vec = vec![];
             io::copy(&mut reader, &mut vec)?;
-            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
 
-            Box::new(crate::archive::zip::list_archive(zip_archive))
+            Box::new(crate::archive::sevenz::list_archive(io::Cursor::new(vec))?)
+        }
+        Rar => {
+            // The unrar library can only read archives from the file system, so the
+            // decompressed stream is spilled into a temporary file first
+            let mut temp_file = tempfile::NamedTempFile::new()?;
+            io::copy(&mut reader, &mut temp_file)?;
+
+            let files: Vec<_> = crate::archive::rar::list_archive(temp_file.path())?.collect();
+
+            Box::new(files.into_iter())
         }
-        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd => {
+        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd | Brotli => {
             panic!("Not an archive! This should never happen, if it does, something is wrong with `CompressionFormat::is_archive()`. Please report this error!");
         }
     };
-    list::list_files(archive_path, files, list_options)?;
+    list::list_files(archive_path, &formats, files, list_options)?;
     Ok(())
 }
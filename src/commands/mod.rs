@@ -1,34 +1,81 @@
 //! Receive command from the cli and call the respective function for that command.
 
+mod cat;
 mod compress;
+mod convert;
 mod decompress;
 mod list;
+mod modify;
+mod test;
 
-use std::{ops::ControlFlow, path::PathBuf};
+use std::{
+    ffi::OsString,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    slice,
+};
 
 use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use same_file::Handle;
 use utils::colors;
 
 use crate::{
     check,
-    cli::Subcommand,
-    commands::{compress::compress_files, decompress::decompress_file, list::list_archive_contents},
+    cli::{ListOutput, Subcommand},
+    commands::{
+        cat::cat_file,
+        compress::compress_files,
+        convert::convert_file,
+        decompress::decompress_file,
+        list::list_archive_contents,
+        modify::{modify_archive, Modification},
+        test::test_file,
+    },
     error::{Error, FinalError},
     extension::{self, parse_format},
     info,
     list::ListOptions,
-    utils::{self, to_utf, EscapedPathDisplay, FileVisibilityPolicy},
-    warning, CliArgs, QuestionPolicy,
+    progress::Progress,
+    utils::{
+        self, pretty_format_list_of_paths, to_utf, DecompressionLimits, EntryFilter, EscapedPathDisplay,
+        FileVisibilityPolicy, Reproducible,
+    },
+    warning, CliArgs, QuestionPolicy, BUFFER_CAPACITY,
 };
 
-/// Warn the user that (de)compressing this .zip archive might freeze their system.
-fn warn_user_about_loading_zip_in_memory() {
-    const ZIP_IN_MEMORY_LIMITATION_WARNING: &str = "\n\
-        \tThe format '.zip' is limited and cannot be (de)compressed using encoding streams.\n\
-        \tWhen using '.zip' with other formats, (de)compression must be done in-memory\n\
+/// Directory next to `archive_path` where a zip archive read through decoders, or what's left of it, is stored
+/// when it has to be, where there should be room for it, `None` when it's read from stdin,
+/// see `archive::zip::spill_file`
+fn zip_spill_dir(archive_path: &Path) -> Option<&Path> {
+    match archive_path.parent() {
+        _ if utils::is_stdio_path(archive_path) => None,
+        Some(parent) if !parent.as_os_str().is_empty() => Some(parent),
+        _ => Some(Path::new(".")),
+    }
+}
+
+/// Write a zip archive read through decoders to an anonymous temporary file, deleted once closed,
+/// because printing and converting it needs the central directory at its end, which requires
+/// io::Seek, unlike decompressing, testing and listing it, see `archive::zip::unpack_archive_stream`
+fn spill_zip_to_temp_file(reader: &mut dyn Read, archive_path: &Path) -> crate::Result<BufReader<std::fs::File>> {
+    let file = crate::archive::zip::spill_file(zip_spill_dir(archive_path))?;
+    let mut writer = BufWriter::with_capacity(BUFFER_CAPACITY, file);
+    io::copy(reader, &mut writer)?;
+    let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.rewind()?;
+
+    Ok(BufReader::with_capacity(BUFFER_CAPACITY, file))
+}
+
+/// Warn the user that (de)compressing this .7z archive might freeze their system.
+fn warn_user_about_loading_sevenz_in_memory() {
+    const SEVENZ_IN_MEMORY_LIMITATION_WARNING: &str = "\n\
+        \tThe format '.7z' is limited and cannot be (de)compressed using encoding streams.\n\
+        \tWhen using '.7z' with other formats, (de)compression must be done in-memory\n\
         \tCareful, you might run out of RAM if the archive is too large!";
 
-    warning!("{}", ZIP_IN_MEMORY_LIMITATION_WARNING);
+    warning!("{}", SEVENZ_IN_MEMORY_LIMITATION_WARNING);
 }
 
 /// This function checks what command needs to be run and performs A LOT of ahead-of-time checks
@@ -47,6 +94,10 @@ pub fn run(
             level,
             fast,
             slow,
+            follow_symlinks,
+            reproducible,
+            source_date_epoch,
+            ..
         } => {
             // After cleaning, if there are no input files left, exit
             if files.is_empty() {
@@ -62,6 +113,12 @@ pub fn run(
                 None => (None, extension::extensions_from_path(&output_path)),
             };
 
+            let output_is_stdout = utils::is_stdio_path(&output_path);
+
+            if output_is_stdout {
+                check::check_format_flag_when_writing_to_stdout(formats_from_flag.as_ref())?;
+            }
+
             check::check_invalid_compression_with_non_archive_format(
                 &formats,
                 &output_path,
@@ -69,10 +126,16 @@ pub fn run(
                 formats_from_flag.as_ref(),
             )?;
             check::check_archive_formats_position(&formats, &output_path)?;
+            check::check_formats_support_compression(&formats, &output_path)?;
+            check::check_format_options_apply(&formats, &output_path)?;
 
-            let output_file = match utils::ask_to_create_file(&output_path, question_policy)? {
-                Some(writer) => writer,
-                None => return Ok(()),
+            let output_file = if output_is_stdout {
+                None
+            } else {
+                match utils::ask_to_create_file(&output_path, question_policy)? {
+                    Some(file) => Some(file),
+                    None => return Ok(()),
+                }
             };
 
             let level = if fast {
@@ -83,6 +146,12 @@ pub fn run(
                 level
             };
 
+            if reproducible {
+                check::check_reproducible_support_formats(&formats, &output_path)?;
+            }
+            let reproducible = reproducible.then(|| Reproducible::new(source_date_epoch));
+            let file_visibility_policy = file_visibility_policy.sort_by_file_name(reproducible.is_some());
+
             let compress_result = compress_files(
                 files,
                 formats,
@@ -91,6 +160,8 @@ pub fn run(
                 args.quiet,
                 question_policy,
                 file_visibility_policy,
+                follow_symlinks,
+                reproducible,
                 level,
             );
 
@@ -99,8 +170,12 @@ pub fn run(
                 // having a final status message is important especially in an accessibility context
                 // as screen readers may not read a commands exit code, making it hard to reason
                 // about whether the command succeeded without such a message
-                info!(accessible, "Successfully compressed '{}'.", to_utf(&output_path));
-            } else {
+                if output_is_stdout {
+                    info!(accessible, "Successfully compressed to stdout.");
+                } else {
+                    info!(accessible, "Successfully compressed '{}'.", to_utf(&output_path));
+                }
+            } else if !output_is_stdout {
                 // If Ok(false) or Err() occurred, delete incomplete file at `output_path`
                 //
                 // if deleting fails, print an extra alert message pointing
@@ -122,7 +197,14 @@ pub fn run(
 
             compress_result?;
         }
-        Subcommand::Decompress { files, output_dir } => {
+        Subcommand::Decompress {
+            files,
+            output_dir,
+            entries,
+            max_size,
+            max_entries,
+            max_ratio,
+        } => {
             let mut output_paths = vec![];
             let mut formats = vec![];
 
@@ -150,6 +232,20 @@ pub fn run(
 
             check::check_missing_formats_when_decompressing(&files, &formats)?;
 
+            let entry_filter = EntryFilter::new(&entries)?;
+            if !entry_filter.is_empty() {
+                check::check_entry_filter_targets_archives(&files, &formats)?;
+            }
+
+            let limits = DecompressionLimits {
+                max_size,
+                max_entries,
+                max_ratio,
+            };
+            if !limits.is_unlimited() {
+                check::check_limits_support_formats(&files, &formats)?;
+            }
+
             // The directory that will contain the output files
             // We default to the current directory if the user didn't specify an output directory with --dir
             let output_dir = if let Some(dir) = output_dir {
@@ -159,23 +255,52 @@ pub fn run(
                 PathBuf::from(".")
             };
 
+            // Archives are decompressed in parallel, one progress is shared by all of them
+            // The total is unknown if one of them is read from stdin
+            let total_size = || {
+                files
+                    .iter()
+                    .map(|file| {
+                        Some(file)
+                            .filter(|file| !utils::is_stdio_path(file))
+                            .and_then(|file| std::fs::metadata(file).ok())
+                            .map(|metadata| metadata.len())
+                    })
+                    .sum()
+            };
+            let progress = Progress::new("Decompressing", total_size, args.quiet);
+
             files
                 .par_iter()
                 .zip(formats)
                 .zip(output_paths)
                 .try_for_each(|((input_path, formats), file_name)| {
+                    // Input read from stdin has no name of its own to give to the output
+                    let file_name = if utils::is_stdio_path(file_name) {
+                        Path::new("stdin")
+                    } else {
+                        file_name
+                    };
                     let output_file_path = output_dir.join(file_name); // Path used by single file format archives
                     decompress_file(
                         input_path,
                         formats,
                         &output_dir,
                         output_file_path,
+                        &entry_filter,
+                        limits,
                         question_policy,
+                        &progress,
                         args.quiet,
                     )
                 })?;
         }
-        Subcommand::List { archives: files, tree } => {
+        Subcommand::List {
+            archives: files,
+            tree,
+            long,
+            output,
+        } => {
             let mut formats = vec![];
 
             if let Some(format) = args.format {
@@ -198,16 +323,291 @@ pub fn run(
             // Ensure we were not told to list the content of a non-archive compressed file
             check::check_for_non_archive_formats(&files, &formats)?;
 
-            let list_options = ListOptions { tree };
+            let list_options = ListOptions { tree, long, output };
 
+            // Archives are separated by an empty line, or are elements of a single JSON array
+            if output == ListOutput::Json {
+                print!("[");
+            }
             for (i, (archive_path, formats)) in files.iter().zip(formats).enumerate() {
                 if i > 0 {
-                    println!();
+                    match output {
+                        ListOutput::Text => println!(),
+                        ListOutput::Json => print!(","),
+                        ListOutput::Ndjson => {}
+                    }
                 }
                 let formats = extension::flatten_compression_formats(&formats);
                 list_archive_contents(archive_path, formats, list_options, question_policy)?;
             }
+            if output == ListOutput::Json {
+                println!("]");
+            }
+        }
+        Subcommand::Test { files } => {
+            let mut formats = vec![];
+
+            if let Some(format) = args.format {
+                let format = parse_format(&format)?;
+                for _ in 0..files.len() {
+                    formats.push(format.clone());
+                }
+            } else {
+                for path in files.iter() {
+                    let mut file_formats = extension::extensions_from_path(path);
+
+                    if let ControlFlow::Break(_) = check::check_mime_type(path, &mut file_formats, question_policy)? {
+                        return Ok(());
+                    }
+
+                    formats.push(file_formats);
+                }
+            }
+
+            check::check_missing_formats_when_decompressing(&files, &formats)?;
+
+            let mut failed_files = vec![];
+            let mut skipped_files = vec![];
+
+            for (input_path, formats) in files.iter().zip(formats) {
+                let path = EscapedPathDisplay::new(input_path);
+
+                match test_file(input_path, formats, question_policy) {
+                    Ok(ControlFlow::Continue(())) => println!("{}OK{}   {path}", *colors::GREEN, *colors::RESET),
+                    Ok(ControlFlow::Break(())) => {
+                        println!("{}SKIP{} {path}", *colors::YELLOW, *colors::RESET);
+                        skipped_files.push(input_path);
+                    }
+                    Err(err) => {
+                        println!("{}FAIL{} {path}", *colors::RED, *colors::RESET);
+                        eprintln!("{err}");
+                        failed_files.push(input_path);
+                    }
+                }
+            }
+
+            if !failed_files.is_empty() {
+                let error = FinalError::with_title("Integrity test failed").detail(format!(
+                    "Files that failed the test: {}",
+                    pretty_format_list_of_paths(&failed_files)
+                ));
+
+                return Err(error.into());
+            }
+
+            // this is only printed once, so it doesn't result in much text. On the other hand,
+            // having a final status message is important especially in an accessibility context
+            if skipped_files.is_empty() {
+                info!(accessible, "All {} files passed the integrity test.", files.len());
+            } else {
+                info!(
+                    accessible,
+                    "{} of {} files passed the integrity test.",
+                    files.len() - skipped_files.len(),
+                    files.len()
+                );
+                warning!(
+                    "Files that were not tested: {}",
+                    pretty_format_list_of_paths(&skipped_files)
+                );
+            }
+        }
+        Subcommand::Convert {
+            input: input_path,
+            output: output_path,
+            level,
+            fast,
+            slow,
+        } => {
+            let mut input_formats = extension::extensions_from_path(&input_path);
+
+            if let ControlFlow::Break(_) = check::check_mime_type(&input_path, &mut input_formats, question_policy)? {
+                return Ok(());
+            }
+
+            check::check_missing_formats_when_decompressing(
+                slice::from_ref(&input_path),
+                slice::from_ref(&input_formats),
+            )?;
+
+            // Like in compression, formats from the flag or from the output path extension
+            let (formats_from_flag, output_formats) = match args.format {
+                Some(formats) => {
+                    let parsed_formats = parse_format(&formats)?;
+                    (Some(formats), parsed_formats)
+                }
+                None => (None, extension::extensions_from_path(&output_path)),
+            };
+
+            let output_is_stdout = utils::is_stdio_path(&output_path);
+
+            if output_is_stdout {
+                check::check_format_flag_when_writing_to_stdout(formats_from_flag.as_ref())?;
+            }
+
+            check::check_first_format_when_compressing(&output_formats, &output_path)?;
+            check::check_archive_formats_position(&output_formats, &output_path)?;
+            check::check_formats_support_compression(&output_formats, &output_path)?;
+            // Options can also be needed to decompress the input, like zstd.dict
+            check::check_format_options_apply(&[input_formats.as_slice(), &output_formats].concat(), &output_path)?;
+
+            if matches!((Handle::from_path(&input_path), Handle::from_path(&output_path)), (Ok(a), Ok(b)) if a == b) {
+                let error =
+                    FinalError::with_title(format!("Cannot convert '{}'.", EscapedPathDisplay::new(&input_path)))
+                        .detail("The input and the output are the same file")
+                        .hint("Choose another path for the output");
+
+                return Err(error.into());
+            }
+
+            let output_file: Box<dyn Write + Send> = if output_is_stdout {
+                Box::new(io::stdout())
+            } else {
+                match utils::ask_to_create_file(&output_path, question_policy)? {
+                    Some(writer) => Box::new(writer),
+                    None => return Ok(()),
+                }
+            };
+
+            let level = if fast {
+                Some(1) // Lowest level of compression
+            } else if slow {
+                Some(i16::MAX) // Highest level of compression
+            } else {
+                level
+            };
+
+            let convert_result = convert_file(
+                &input_path,
+                input_formats,
+                output_file,
+                &output_path,
+                output_formats,
+                args.quiet,
+                level,
+            );
+
+            if convert_result.is_ok() {
+                // this is only printed once, so it doesn't result in much text. On the other hand,
+                // having a final status message is important especially in an accessibility context
+                if output_is_stdout {
+                    info!(accessible, "Successfully converted to stdout.");
+                } else {
+                    info!(accessible, "Successfully converted to '{}'.", to_utf(&output_path));
+                }
+            } else if !output_is_stdout {
+                // If failed, delete the incomplete file at `output_path`, see compression
+                if utils::remove_file_or_dir(&output_path).is_err() {
+                    eprintln!("{red}FATAL ERROR:\n", red = *colors::RED);
+                    eprintln!(
+                        "  Ouch failed to delete the file '{}'.",
+                        EscapedPathDisplay::new(&output_path)
+                    );
+                    eprintln!("  Please delete it manually.");
+                    eprintln!("  This file is corrupted if conversion didn't finished.");
+                }
+            }
+
+            convert_result?;
+        }
+        Subcommand::Cat { file, entry } => {
+            let formats = if let Some(format) = args.format {
+                parse_format(&format)?
+            } else {
+                let mut formats = extension::extensions_from_path(&file);
+
+                // Questions would be printed to stdout, mixed with the output, so the detected format is
+                // used without asking, and only reported on stderr
+                if let ControlFlow::Break(_) = check::check_mime_type(&file, &mut formats, QuestionPolicy::AlwaysYes)? {
+                    unreachable!("questions are always answered with yes");
+                }
+
+                formats
+            };
+
+            check::check_missing_formats_when_decompressing(slice::from_ref(&file), slice::from_ref(&formats))?;
+
+            cat_file(&file, formats, entry.as_deref())?;
+        }
+        Subcommand::Add {
+            archive,
+            files,
+            follow_symlinks,
+        } => {
+            let modification = Modification::Add {
+                files,
+                only_newer: false,
+            };
+            modify(
+                &archive,
+                args.format,
+                modification,
+                file_visibility_policy,
+                follow_symlinks,
+                args.quiet,
+            )?;
+        }
+        Subcommand::Update {
+            archive,
+            files,
+            follow_symlinks,
+        } => {
+            let modification = Modification::Add {
+                files,
+                only_newer: true,
+            };
+            modify(
+                &archive,
+                args.format,
+                modification,
+                file_visibility_policy,
+                follow_symlinks,
+                args.quiet,
+            )?;
+        }
+        Subcommand::Delete { archive, entries } => {
+            let modification = Modification::Delete(EntryFilter::new(&entries)?);
+            modify(
+                &archive,
+                args.format,
+                modification,
+                file_visibility_policy,
+                false,
+                args.quiet,
+            )?;
         }
     }
     Ok(())
 }
+
+/// Shared by the subcommands that modify archives, `add`, `update` and `delete`
+fn modify(
+    archive: &Path,
+    format: Option<OsString>,
+    modification: Modification,
+    file_visibility_policy: FileVisibilityPolicy,
+    follow_symlinks: bool,
+    quiet: bool,
+) -> crate::Result<()> {
+    let formats = match format {
+        Some(format) => parse_format(&format)?,
+        None => extension::extensions_from_path(archive),
+    };
+
+    check::check_archive_can_be_modified(&formats, archive)?;
+
+    if modify_archive(
+        archive,
+        formats,
+        modification,
+        file_visibility_policy,
+        follow_symlinks,
+        quiet,
+    )? {
+        info!(accessible, "Successfully updated '{}'.", to_utf(archive));
+    } else {
+        info!(accessible, "Nothing to change in '{}'.", to_utf(archive));
+    }
+
+    Ok(())
+}
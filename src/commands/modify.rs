@@ -0,0 +1,311 @@
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    env,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use same_file::Handle;
+
+use crate::{
+    archive,
+    commands::{compress::chain_writer_encoder, decompress::chain_reader_decoder},
+    extension::{
+        split_first_compression_format,
+        CompressionFormat::{self, *},
+        Extension,
+    },
+    info,
+    list::FileInArchive,
+    progress::Progress,
+    utils::{self, without_cur_dir, EntryFilter, EscapedPathDisplay, FileVisibilityPolicy},
+    BUFFER_CAPACITY,
+};
+
+/// What `modify_archive` changes in an archive
+pub enum Modification {
+    /// Add the files, replacing the entries with the same paths, only those that are older
+    /// than their files if `only_newer` is set
+    Add { files: Vec<PathBuf>, only_newer: bool },
+    /// Remove the entries selected by the filter, directories are removed with their contents
+    Delete(EntryFilter),
+}
+
+// Modify the archive at archive_path in place
+//
+// formats contains each format of the archive, example: [Tar, Gz], only tar archives, compressed or not,
+// and zip archives are supported, see `check::check_archive_can_be_modified`
+//
+// Tar archives are rewritten through the decoder and encoder chains to a temporary file, which replaces
+// the archive once complete, after a first pass finding the targets of hard links. Files are appended to
+// zip archives in place, unless entries have to be replaced or removed, in which case the other entries
+// are copied to a temporary file as they are, without being decompressed
+//
+// Returns `Ok(false)` if there was nothing to change
+pub fn modify_archive(
+    archive_path: &Path,
+    formats: Vec<Extension>,
+    modification: Modification,
+    file_visibility_policy: FileVisibilityPolicy,
+    follow_symlinks: bool,
+    quiet: bool,
+) -> crate::Result<bool> {
+    let (first_format, formats) = split_first_compression_format(&formats);
+
+    let (files, added) = match &modification {
+        Modification::Add { files, only_newer } => {
+            let old_entries = if *only_newer {
+                list_entries(archive_path, first_format, &formats)?
+            } else {
+                HashMap::new()
+            };
+
+            let added: HashSet<PathBuf> = walk_files(files, archive_path, &file_visibility_policy, follow_symlinks)?
+                .into_iter()
+                .filter(|(path, metadata)| !*only_newer || is_newer(metadata, old_entries.get(path), first_format))
+                .map(|(path, _)| path)
+                .collect();
+
+            if added.is_empty() {
+                return Ok(false);
+            }
+
+            (files.as_slice(), added)
+        }
+        Modification::Delete(_) => (&[][..], HashSet::new()),
+    };
+
+    let removed = Cell::new(0);
+    let keep = |path: &Path| {
+        let path = without_cur_dir(path);
+        let is_removed = match &modification {
+            Modification::Add { .. } => added.contains(&path),
+            Modification::Delete(filter) => filter.is_selected(&path),
+        };
+
+        if is_removed {
+            removed.set(removed.get() + 1);
+            // Added files are already reported as they are compressed
+            if !quiet && matches!(modification, Modification::Delete(_)) {
+                info!(inaccessible, "Removing '{}'.", EscapedPathDisplay::new(&path));
+            }
+        }
+
+        !is_removed
+    };
+    let select = |path: &Path| added.contains(path);
+
+    match first_format {
+        Tar => rewrite(archive_path, |temp_path, file| {
+            let mut writer: Box<dyn Write + Send> = Box::new(BufWriter::with_capacity(BUFFER_CAPACITY, file));
+            for format in formats.iter().rev() {
+                writer = chain_writer_encoder(format, writer, None)?;
+            }
+
+            // Read once more beforehand, links can only be resolved in order
+            let link_targets =
+                archive::tar::hard_link_targets(tar::Archive::new(open_decoded(archive_path, &formats)?))?;
+
+            let mut builder = tar::Builder::new(writer);
+            let reader = open_decoded(archive_path, &formats)?;
+            archive::tar::copy_entries(tar::Archive::new(reader), &mut builder, &keep, &link_targets)?;
+            archive::tar::append_paths(
+                &mut builder,
+                files,
+                temp_path,
+                &file_visibility_policy,
+                follow_symlinks,
+                None,
+                &select,
+                &Progress::default(),
+                quiet,
+            )?;
+            builder.into_inner()?.flush()?;
+
+            Ok(!added.is_empty() || removed.get() > 0)
+        }),
+        Zip => {
+            let mut zip_archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
+
+            // Going through the names first avoids rewriting archives that lose no entries
+            let loses_entries = zip_archive.file_names().any(|name| {
+                let path = without_cur_dir(Path::new(name));
+                match &modification {
+                    Modification::Add { .. } => added.contains(&path),
+                    Modification::Delete(filter) => filter.is_selected(&path),
+                }
+            });
+
+            if !loses_entries {
+                if added.is_empty() {
+                    return Ok(false);
+                }
+
+                let file = fs::OpenOptions::new().read(true).write(true).open(archive_path)?;
+                let mut writer = zip::ZipWriter::new_append(file)?;
+                archive::zip::append_paths(
+                    &mut writer,
+                    files,
+                    archive_path,
+                    &file_visibility_policy,
+                    follow_symlinks,
+                    None,
+                    &select,
+                    &Progress::default(),
+                    quiet,
+                )?;
+                writer.finish()?;
+
+                return Ok(true);
+            }
+
+            rewrite(archive_path, |temp_path, file| {
+                let mut writer = zip::ZipWriter::new(file);
+                archive::zip::copy_entries(&mut zip_archive, &mut writer, &keep)?;
+                archive::zip::append_paths(
+                    &mut writer,
+                    files,
+                    temp_path,
+                    &file_visibility_policy,
+                    follow_symlinks,
+                    None,
+                    &select,
+                    &Progress::default(),
+                    quiet,
+                )?;
+                writer.finish()?;
+
+                Ok(true)
+            })
+        }
+        _ => unreachable!("checked by `check::check_archive_can_be_modified`"),
+    }
+}
+
+/// Write a new version of the archive at `archive_path` with `write`, which returns whether it
+/// changed anything, and replace the archive with it
+///
+/// The new version is written next to the archive, so it can be renamed over it, and it's deleted if
+/// `write` fails, leaving the archive untouched.
+fn rewrite(
+    archive_path: &Path,
+    write: impl FnOnce(&Path, std::fs::File) -> crate::Result<bool>,
+) -> crate::Result<bool> {
+    // Safe unwrap, the archive path was canonicalized
+    let temp_file = tempfile::NamedTempFile::new_in(archive_path.parent().unwrap())?;
+
+    if !write(temp_file.path(), temp_file.reopen()?)? {
+        return Ok(false);
+    }
+
+    fs::set_permissions(temp_file.path(), fs::metadata(archive_path)?.permissions())?;
+    temp_file.persist(archive_path).map_err(|err| err.error)?;
+
+    Ok(true)
+}
+
+/// Open the archive at `archive_path` through the decoders of `formats`
+fn open_decoded(archive_path: &Path, formats: &[CompressionFormat]) -> crate::Result<Box<dyn Read + Send>> {
+    let mut reader: Box<dyn Read + Send> =
+        Box::new(BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(archive_path)?));
+
+    for format in formats.iter().rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+
+    Ok(reader)
+}
+
+/// Entries of the archive at `archive_path`, by path
+fn list_entries(
+    archive_path: &Path,
+    first_format: CompressionFormat,
+    formats: &[CompressionFormat],
+) -> crate::Result<HashMap<PathBuf, FileInArchive>> {
+    let entries: Box<dyn Iterator<Item = crate::Result<FileInArchive>>> = match first_format {
+        Tar => Box::new(archive::tar::list_archive(tar::Archive::new(open_decoded(
+            archive_path,
+            formats,
+        )?))),
+        Zip => Box::new(archive::zip::list_archive(zip::ZipArchive::new(fs::File::open(
+            archive_path,
+        )?)?)),
+        _ => unreachable!("checked by `check::check_archive_can_be_modified`"),
+    };
+
+    entries
+        .map(|entry| entry.map(|entry| (without_cur_dir(&entry.path), entry)))
+        .collect()
+}
+
+/// Paths that the files and the contents of the directories in `files` get in the archive,
+/// with their metadata, like `archive::tar::append_paths` walks them
+fn walk_files(
+    files: &[PathBuf],
+    archive_path: &Path,
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+) -> crate::Result<Vec<(PathBuf, std::fs::Metadata)>> {
+    let archive_handle = Handle::from_path(archive_path);
+    let mut walked = vec![];
+
+    for filename in files {
+        let previous_location = utils::cd_into_same_dir_as(filename)?;
+
+        // Safe unwrap, input shall be treated before
+        let filename = filename.file_name().unwrap();
+
+        for entry in file_visibility_policy.build_walker(filename) {
+            let entry = entry?;
+            let path = entry.path();
+
+            // The archive can't be added to itself
+            if let Ok(ref handle) = archive_handle {
+                if matches!(Handle::from_path(path), Ok(x) if &x == handle) {
+                    continue;
+                }
+            }
+
+            let metadata = if follow_symlinks {
+                std::fs::metadata(path)
+            } else {
+                std::fs::symlink_metadata(path)
+            };
+
+            // Broken symlinks are skipped when compressing
+            if let Ok(metadata) = metadata {
+                walked.push((path.to_path_buf(), metadata));
+            }
+        }
+
+        env::set_current_dir(previous_location)?;
+    }
+
+    Ok(walked)
+}
+
+/// Whether a file with `metadata` should replace the `archived` entry with the same path
+fn is_newer(metadata: &std::fs::Metadata, archived: Option<&FileInArchive>, format: CompressionFormat) -> bool {
+    let Some(archived) = archived else {
+        return true;
+    };
+
+    if metadata.is_dir() {
+        return false;
+    }
+
+    // Zip archives store modification times with a precision of 2 seconds
+    let precision = if format == Zip { 2 } else { 1 };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() as i64);
+
+    match (modified, archived.mtime) {
+        (Some(modified), Some(archived)) => modified >= archived + precision,
+        _ => true,
+    }
+}
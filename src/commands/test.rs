@@ -0,0 +1,110 @@
+use std::{
+    io::{self, BufReader, Read},
+    ops::ControlFlow,
+    path::Path,
+};
+
+use fs_err as fs;
+
+use crate::{
+    commands::{decompress::chain_reader_decoder, warn_user_about_loading_sevenz_in_memory, zip_spill_dir},
+    extension::{split_first_compression_format, CompressionFormat::*, Extension},
+    utils::{self, user_wants_to_continue},
+    QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
+};
+
+// Test the integrity of a file, without extracting anything to disk
+//
+// File at input_file_path is opened for reading, example: "archive.tar.gz"
+// formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+//
+// The file goes through the same decoder chain used by `decompress_file`, and archive
+// entries are read to the end, so that truncated streams and checksum mismatches are caught
+pub fn test_file(
+    input_file_path: &Path,
+    formats: Vec<Extension>,
+    question_policy: QuestionPolicy,
+) -> crate::Result<ControlFlow<()>> {
+    // Input read from stdin can't be seeked, so it always goes through decoder chaining
+    let input_is_stdin = utils::is_stdio_path(input_file_path);
+
+    // Zip, 7z and RAR archives can be read directly when they are not wrapped by other formats,
+    // see `decompress_file`
+    match (input_is_stdin, formats.as_slice()) {
+        (
+            false,
+            [Extension {
+                compression_formats: [Zip],
+                ..
+            }],
+        ) => {
+            crate::archive::zip::test_archive(zip::ZipArchive::new(fs::File::open(input_file_path)?)?)?;
+            return Ok(ControlFlow::Continue(()));
+        }
+        (
+            false,
+            [Extension {
+                compression_formats: [SevenZip],
+                ..
+            }],
+        ) => {
+            crate::archive::sevenz::test_archive(fs::File::open(input_file_path)?)?;
+            return Ok(ControlFlow::Continue(()));
+        }
+        (
+            false,
+            [Extension {
+                compression_formats: [Rar],
+                ..
+            }],
+        ) => {
+            crate::archive::rar::test_archive(input_file_path)?;
+            return Ok(ControlFlow::Continue(()));
+        }
+        _ => {}
+    }
+
+    // Will be used in decoder chaining
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, utils::open_file_or_stdin(input_file_path)?);
+    let mut reader: Box<dyn Read + Send> = Box::new(reader);
+
+    let (first_extension, extensions) = split_first_compression_format(&formats);
+
+    for format in extensions.iter().rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+
+    match first_extension {
+        Gzip | Bzip | Lz4 | Lzma | Snappy | Zstd | Brotli => {
+            reader = chain_reader_decoder(&first_extension, reader)?;
+            io::copy(&mut reader, &mut io::sink())?;
+        }
+        Tar => crate::archive::tar::test_archive(reader)?,
+        Zip => {
+            crate::archive::zip::test_archive_stream(reader, zip_spill_dir(input_file_path))?;
+        }
+        SevenZip => {
+            warn_user_about_loading_sevenz_in_memory();
+
+            // Questions are answered through stdin, which is already taken by the archive
+            if !input_is_stdin
+                && !user_wants_to_continue(input_file_path, question_policy, QuestionAction::Decompression)?
+            {
+                return Ok(ControlFlow::Break(()));
+            }
+
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
+            crate::archive::sevenz::test_archive(io::Cursor::new(vec))?;
+        }
+        Rar => {
+            // The unrar library can only read archives from the file system, so the
+            // decompressed stream is spilled into a temporary file first
+            let mut temp_file = tempfile::NamedTempFile::new()?;
+            io::copy(&mut reader, &mut temp_file)?;
+            crate::archive::rar::test_archive(temp_file.path())?;
+        }
+    }
+
+    Ok(ControlFlow::Continue(()))
+}
@@ -7,7 +7,10 @@ use std::{
     fmt::{self, Display},
 };
 
-use crate::{accessible::is_running_in_accessible_mode, utils::colors::*};
+use crate::{
+    accessible::is_running_in_accessible_mode,
+    utils::{colors::*, LimitExceeded},
+};
 
 /// All errors that can be generated by `ouch`
 #[derive(Debug)]
@@ -34,6 +37,10 @@ pub enum Error {
     Custom { reason: FinalError },
     /// Invalid format passed to `--format`
     InvalidFormat { reason: String },
+    /// From sevenz_rust::Error
+    SevenzipError { reason: String },
+    /// A limit given to `ouch decompress` was exceeded, detected from io::Error when it wraps one
+    LimitExceeded(LimitExceeded),
 }
 
 /// Alias to std's Result with ouch's Error
@@ -138,6 +145,18 @@ impl fmt::Display for Error {
             }
             Error::UnsupportedZipArchive(reason) => FinalError::with_title("Unsupported zip archive").detail(*reason),
             Error::InvalidFormat { reason } => FinalError::with_title("Invalid archive format").detail(reason.clone()),
+            Error::SevenzipError { reason } => FinalError::with_title("7z error").detail(reason.to_string()),
+            Error::LimitExceeded(limit) => {
+                let flag = match limit {
+                    LimitExceeded::Size(_) => "--max-size",
+                    LimitExceeded::Entries(_) => "--max-entries",
+                    LimitExceeded::Ratio(_) => "--max-ratio",
+                };
+                FinalError::with_title("Decompression aborted, a limit was exceeded")
+                    .detail(limit.to_string())
+                    .detail("Files extracted so far were removed")
+                    .hint(format!("If the file is trusted, raise the limit with {flag}"))
+            }
             Error::Custom { reason } => reason.clone(),
         };
 
@@ -147,6 +166,10 @@ impl fmt::Display for Error {
 
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
+        if let Some(limit) = LimitExceeded::find_in(&err) {
+            return Self::LimitExceeded(limit);
+        }
+
         match err.kind() {
             std::io::ErrorKind::NotFound => Self::NotFound {
                 error_title: err.to_string(),
@@ -186,6 +209,26 @@ impl From<zip::result::ZipError> for Error {
     }
 }
 
+impl From<sevenz_rust::Error> for Error {
+    fn from(err: sevenz_rust::Error) -> Self {
+        match err {
+            sevenz_rust::Error::Io(io_err, _) | sevenz_rust::Error::FileOpen(io_err, _) => Self::from(io_err),
+            other => Self::SevenzipError {
+                reason: other.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "unrar")]
+impl From<unrar::error::UnrarError> for Error {
+    fn from(err: unrar::error::UnrarError) -> Self {
+        Self::Custom {
+            reason: FinalError::with_title("Unexpected error in rar archive").detail(err.to_string()),
+        }
+    }
+}
+
 impl From<ignore::Error> for Error {
     fn from(err: ignore::Error) -> Self {
         Self::WalkdirError {
@@ -194,6 +237,12 @@ impl From<ignore::Error> for Error {
     }
 }
 
+impl From<LimitExceeded> for Error {
+    fn from(limit: LimitExceeded) -> Self {
+        Self::LimitExceeded(limit)
+    }
+}
+
 impl From<FinalError> for Error {
     fn from(err: FinalError) -> Self {
         Self::Custom { reason: err }
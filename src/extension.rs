@@ -3,6 +3,7 @@
 use std::{ffi::OsStr, fmt, path::Path};
 
 use bstr::ByteSlice;
+use serde::Serialize;
 
 use self::CompressionFormat::*;
 use crate::{error::Error, warning};
@@ -48,46 +49,66 @@ impl fmt::Display for Extension {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
 /// Accepted extensions for input and output
+///
+/// Serialized as the most common extension of each format, used by `ouch list --output json`
 pub enum CompressionFormat {
     /// .gz
+    #[serde(rename = "gz")]
     Gzip,
     /// .bz .bz2
+    #[serde(rename = "bz2")]
     Bzip,
     /// .lz4
+    #[serde(rename = "lz4")]
     Lz4,
     /// .xz .lzma
+    #[serde(rename = "xz")]
     Lzma,
     /// .sz
+    #[serde(rename = "sz")]
     Snappy,
-    /// tar, tgz, tbz, tbz2, txz, tlz4, tlzma, tsz, tzst
+    /// .7z
+    #[serde(rename = "7z")]
+    SevenZip,
+    /// .rar, can only be decompressed
+    #[serde(rename = "rar")]
+    Rar,
+    /// tar, tgz, tbz, tbz2, txz, tlz4, tlzma, tsz, tzst, tbr
+    #[serde(rename = "tar")]
     Tar,
     /// .zst
+    #[serde(rename = "zst")]
     Zstd,
+    /// .br
+    #[serde(rename = "br")]
+    Brotli,
     /// .zip
+    #[serde(rename = "zip")]
     Zip,
 }
 
 impl CompressionFormat {
-    /// Currently supported archive formats are .tar (and aliases to it) and .zip
+    /// Currently supported archive formats are .tar (and aliases to it), .zip, .7z and .rar
     fn is_archive_format(&self) -> bool {
         // Keep this match like that without a wildcard `_` so we don't forget to update it
         match self {
-            Tar | Zip => true,
+            Tar | Zip | SevenZip | Rar => true,
             Gzip => false,
             Bzip => false,
             Lz4 => false,
             Lzma => false,
             Snappy => false,
             Zstd => false,
+            Brotli => false,
         }
     }
 }
 
 pub const SUPPORTED_EXTENSIONS: &[&str] = &[
-    "tar", "tgz", "tbz", "tlz4", "txz", "tzlma", "tsz", "tzst", "zip", "bz", "bz2", "gz", "lz4", "xz", "lzma", "sz",
-    "zst",
+    "tar", "tgz", "tbz", "tlz4", "txz", "tzlma", "tsz", "tzst", "tbr", "zip", "7z", "rar", "bz", "bz2", "gz", "lz4",
+    "xz", "lzma", "sz", "zst", "br",
 ];
 
 fn to_extension(ext: &[u8]) -> Option<Extension> {
@@ -100,13 +121,17 @@ fn to_extension(ext: &[u8]) -> Option<Extension> {
             b"txz" | b"tlzma" => &[Tar, Lzma],
             b"tsz" => &[Tar, Snappy],
             b"tzst" => &[Tar, Zstd],
+            b"tbr" => &[Tar, Brotli],
             b"zip" => &[Zip],
+            b"7z" => &[SevenZip],
+            b"rar" => &[Rar],
             b"bz" | b"bz2" => &[Bzip],
             b"gz" => &[Gzip],
             b"lz4" => &[Lz4],
             b"xz" | b"lzma" => &[Lzma],
             b"sz" => &[Snappy],
             b"zst" => &[Zstd],
+            b"br" => &[Brotli],
             _ => return None,
         },
         ext.to_str_lossy(),
@@ -5,43 +5,114 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use serde::{Serialize, Serializer};
+
 use self::tree::Tree;
-use crate::{accessible::is_running_in_accessible_mode, utils::EscapedPathDisplay};
+use crate::{
+    accessible::is_running_in_accessible_mode, cli::ListOutput, extension::CompressionFormat, utils::EscapedPathDisplay,
+};
 
 /// Options controlling how archive contents should be listed
 #[derive(Debug, Clone, Copy)]
 pub struct ListOptions {
     /// Whether to show a tree view
     pub tree: bool,
+    /// Whether to show sizes, permissions and modification times in columns
+    pub long: bool,
+    /// Whether to print text or JSON
+    pub output: ListOutput,
 }
 
 /// Represents a single file in an archive, used in `list::list_files()`
-#[derive(Debug, Clone)]
+///
+/// Archive formats don't all store the same metadata, so everything besides the path is optional.
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct FileInArchive {
     /// The file path
+    #[serde(serialize_with = "serialize_path")]
     pub path: PathBuf,
 
+    /// The type of this entry
+    #[serde(rename = "type")]
+    pub kind: EntryKind,
+
+    /// Size of the file once extracted, in bytes
+    pub size: Option<u64>,
+
+    /// Size of the file inside of the archive, in bytes, only known for zip archives
+    pub compressed_size: Option<u64>,
+
+    /// Unix permissions of the file
+    pub mode: Option<u32>,
+
+    /// Last modification time, in seconds since the unix epoch
+    pub mtime: Option<i64>,
+
+    /// Path pointed to by symlinks and hardlinks
+    #[serde(serialize_with = "serialize_optional_path")]
+    pub link_target: Option<PathBuf>,
+}
+
+/// Type of an entry in an archive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    #[default]
+    File,
+    Directory,
+    Symlink,
+    Hardlink,
+    /// Devices, fifos and other special files
+    Other,
+}
+
+impl FileInArchive {
     /// Whether this file is a directory
-    pub is_dir: bool,
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+}
+
+// Paths that aren't valid UTF-8 can't be represented in JSON, so they are converted lossily
+fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn serialize_optional_path<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
 }
 
 /// Actually print the files
 /// Returns an Error, if one of the files can't be read
 pub fn list_files(
     archive: &Path,
+    formats: &[CompressionFormat],
     files: impl IntoIterator<Item = crate::Result<FileInArchive>>,
     list_options: ListOptions,
 ) -> crate::Result<()> {
     let out = &mut stdout().lock();
+
+    match list_options.output {
+        ListOutput::Text => {}
+        ListOutput::Json => return json::print_archive(out, archive, formats, files),
+        ListOutput::Ndjson => return json::print_archive_lines(out, archive, formats, files),
+    }
+
     let _ = writeln!(out, "Archive: {}", EscapedPathDisplay::new(archive));
 
     if list_options.tree {
         let tree = files.into_iter().collect::<crate::Result<Tree>>()?;
         tree.print(out);
+    } else if list_options.long {
+        let files = files.into_iter().collect::<crate::Result<Vec<_>>>()?;
+        long::print(out, &files);
     } else {
         for file in files {
-            let FileInArchive { path, is_dir } = file?;
-            print_entry(out, EscapedPathDisplay::new(&path), is_dir);
+            let file = file?;
+            print_entry(out, EscapedPathDisplay::new(&file.path), file.is_dir());
         }
     }
     Ok(())
@@ -70,6 +141,256 @@ fn print_entry(out: &mut impl Write, name: impl std::fmt::Display, is_dir: bool)
     }
 }
 
+/// Machine readable listing, entries are written as soon as they are read from the archive
+mod json {
+    use std::{
+        cell::{Cell, RefCell},
+        io,
+        io::Write,
+        path::Path,
+    };
+
+    use serde::{ser::SerializeSeq, Serialize, Serializer};
+
+    use super::{serialize_path, FileInArchive};
+    use crate::extension::CompressionFormat;
+
+    #[derive(Serialize)]
+    struct ArchiveHeader<'a> {
+        #[serde(serialize_with = "serialize_path")]
+        archive: &'a Path,
+        formats: &'a [CompressionFormat],
+    }
+
+    #[derive(Serialize)]
+    struct EntryLine<'a> {
+        #[serde(serialize_with = "serialize_path")]
+        archive: &'a Path,
+        #[serde(flatten)]
+        file: &'a FileInArchive,
+    }
+
+    #[derive(Serialize)]
+    struct ArchiveObject<'a> {
+        #[serde(flatten)]
+        header: ArchiveHeader<'a>,
+        entries: Entries<'a>,
+    }
+
+    /// Entries serialized while they're read, so they don't have to be collected first
+    ///
+    /// Serde errors can't carry ours, so the first error reading the entries is kept in `error`.
+    struct Entries<'a> {
+        files: RefCell<&'a mut dyn Iterator<Item = crate::Result<FileInArchive>>>,
+        error: &'a Cell<Option<crate::Error>>,
+    }
+
+    impl Serialize for Entries<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(None)?;
+            for file in &mut **self.files.borrow_mut() {
+                match file {
+                    Ok(file) => seq.serialize_element(&file)?,
+                    Err(err) => {
+                        self.error.set(Some(err));
+                        return Err(serde::ser::Error::custom("could not read the entries"));
+                    }
+                }
+            }
+            seq.end()
+        }
+    }
+
+    /// Write a single object with the archive metadata and an array of its entries,
+    /// `{"archive": ..., "formats": [...], "entries": [...]}`
+    pub fn print_archive(
+        out: &mut impl Write,
+        archive: &Path,
+        formats: &[CompressionFormat],
+        files: impl IntoIterator<Item = crate::Result<FileInArchive>>,
+    ) -> crate::Result<()> {
+        let mut files = files.into_iter();
+        let error = Cell::new(None);
+        let object = ArchiveObject {
+            header: ArchiveHeader { archive, formats },
+            entries: Entries {
+                files: RefCell::new(&mut files),
+                error: &error,
+            },
+        };
+
+        let result = write_json(out, &object);
+        error.into_inner().map_or(result, Err)
+    }
+
+    /// Write the archive metadata in the first line, followed by one line per entry,
+    /// entries also carry the path of their archive, so lines can be filtered independently
+    pub fn print_archive_lines(
+        out: &mut impl Write,
+        archive: &Path,
+        formats: &[CompressionFormat],
+        files: impl IntoIterator<Item = crate::Result<FileInArchive>>,
+    ) -> crate::Result<()> {
+        write_json(out, &ArchiveHeader { archive, formats })?;
+        writeln!(out)?;
+
+        for file in files {
+            write_json(out, &EntryLine { archive, file: &file? })?;
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_json(out: &mut impl Write, value: &impl Serialize) -> crate::Result<()> {
+        serde_json::to_writer(out, value).map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+/// Long listing, showing the metadata of each entry in aligned columns, like `ls -l`
+mod long {
+    use std::io::Write;
+
+    use time::OffsetDateTime;
+
+    use super::{EntryKind, FileInArchive};
+    use crate::utils::EscapedPathDisplay;
+
+    /// Print the entries in columns, followed by a line with the totals
+    pub fn print(out: &mut impl Write, files: &[FileInArchive]) {
+        let has_compressed_sizes = files.iter().any(|file| file.compressed_size.is_some());
+
+        let rows: Vec<Row> = files.iter().map(Row::new).collect();
+        let size_width = rows
+            .iter()
+            .map(|row| row.size.len())
+            .max()
+            .unwrap_or(0)
+            .max("Size".len());
+        let compressed_width = rows
+            .iter()
+            .map(|row| row.compressed_size.len())
+            .max()
+            .unwrap_or(0)
+            .max("Compressed".len());
+
+        let _ = write!(out, "{:<10}  {:>size_width$}  ", "Mode", "Size");
+        if has_compressed_sizes {
+            let _ = write!(out, "{:>compressed_width$}  {:>5}  ", "Compressed", "Ratio");
+        }
+        let _ = writeln!(out, "{:<16}  Name", "Modified");
+
+        for (file, row) in files.iter().zip(&rows) {
+            let _ = write!(out, "{}  {:>size_width$}  ", row.mode, row.size);
+            if has_compressed_sizes {
+                let _ = write!(out, "{:>compressed_width$}  {:>5}  ", row.compressed_size, row.ratio);
+            }
+            let _ = write!(out, "{:<16}  ", row.mtime);
+
+            let name = match &file.link_target {
+                Some(target) => format!(
+                    "{} -> {}",
+                    EscapedPathDisplay::new(&file.path),
+                    EscapedPathDisplay::new(target)
+                ),
+                None => EscapedPathDisplay::new(&file.path).to_string(),
+            };
+            super::print_entry(out, name, file.is_dir());
+        }
+
+        let total_size: u64 = files.iter().filter_map(|file| file.size).sum();
+        let _ = write!(out, "{} entries, {} bytes", files.len(), total_size);
+        if has_compressed_sizes {
+            let total_compressed: u64 = files.iter().filter_map(|file| file.compressed_size).sum();
+            let _ = write!(
+                out,
+                ", {} bytes compressed ({})",
+                total_compressed,
+                ratio(total_compressed, total_size)
+            );
+        }
+        let _ = writeln!(out);
+    }
+
+    /// The columns of an entry, already formatted
+    struct Row {
+        mode: String,
+        size: String,
+        compressed_size: String,
+        ratio: String,
+        mtime: String,
+    }
+
+    impl Row {
+        fn new(file: &FileInArchive) -> Self {
+            let unknown = || "-".to_string();
+
+            Self {
+                mode: format_mode(file.kind, file.mode),
+                size: file.size.map_or_else(unknown, |size| size.to_string()),
+                compressed_size: file.compressed_size.map_or_else(unknown, |size| size.to_string()),
+                ratio: match (file.compressed_size, file.size) {
+                    (Some(compressed_size), Some(size)) => ratio(compressed_size, size),
+                    _ => unknown(),
+                },
+                mtime: file.mtime.and_then(format_mtime).unwrap_or_else(unknown),
+            }
+        }
+    }
+
+    /// Compressed size as a percentage of the original size
+    fn ratio(compressed_size: u64, size: u64) -> String {
+        if size == 0 {
+            return "-".to_string();
+        }
+
+        format!("{:.0}%", compressed_size as f64 / size as f64 * 100.0)
+    }
+
+    /// Format like `ls -l` does, e.g. "drwxr-xr-x", permissions are shown as `?` if unknown
+    fn format_mode(kind: EntryKind, mode: Option<u32>) -> String {
+        let kind = match kind {
+            EntryKind::File => '-',
+            EntryKind::Directory => 'd',
+            EntryKind::Symlink => 'l',
+            EntryKind::Hardlink => 'h',
+            EntryKind::Other => '?',
+        };
+
+        let permissions: String = match mode {
+            Some(mode) => (0..9)
+                .map(|i| {
+                    let is_set = mode & (0o400 >> i) != 0;
+                    match (is_set, i % 3) {
+                        (false, _) => '-',
+                        (true, 0) => 'r',
+                        (true, 1) => 'w',
+                        (true, _) => 'x',
+                    }
+                })
+                .collect(),
+            None => "?".repeat(9),
+        };
+
+        format!("{kind}{permissions}")
+    }
+
+    /// Format as "YYYY-MM-DD HH:MM", in UTC
+    fn format_mtime(mtime: i64) -> Option<String> {
+        let mtime = OffsetDateTime::from_unix_timestamp(mtime).ok()?;
+
+        Some(format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            mtime.year(),
+            mtime.month() as u8,
+            mtime.day(),
+            mtime.hour(),
+            mtime.minute()
+        ))
+    }
+}
+
 /// Since archives store files as a list of entries -> without direct
 /// directory structure (the directories are however part of the name),
 /// we have to construct the tree structure ourselves to be able to
@@ -145,8 +466,8 @@ mod tree {
             };
 
             print!("{prefix}{final_part}");
-            let is_dir = match self.file {
-                Some(FileInArchive { is_dir, .. }) => is_dir,
+            let is_dir = match &self.file {
+                Some(file) => file.is_dir(),
                 None => true,
             };
             super::print_entry(out, <Vec<u8> as ByteVec>::from_os_str_lossy(name).as_bstr(), is_dir);
@@ -21,6 +21,7 @@ macro_rules! info {
     (accessible, $($arg:tt)*) => {{
         use $crate::utils::colors::{YELLOW, RESET};
 
+        $crate::progress::clear_line();
         if $crate::accessible::is_running_in_accessible_mode() {
             eprint!("{}Info:{} ", *YELLOW, *RESET);
         } else {
@@ -35,6 +36,7 @@ macro_rules! info {
         use $crate::utils::colors::{YELLOW, RESET};
 
         if !$crate::accessible::is_running_in_accessible_mode() {
+            $crate::progress::clear_line();
             eprint!("{}[INFO]{} ", *YELLOW, *RESET);
             eprintln!($($arg)*);
         }
@@ -47,6 +49,7 @@ macro_rules! warning {
     ($($arg:tt)*) => {{
         use $crate::utils::colors::{ORANGE, RESET};
 
+        $crate::progress::clear_line();
         if $crate::accessible::is_running_in_accessible_mode() {
             eprint!("{}Warning:{} ", *ORANGE, *RESET);
         } else {
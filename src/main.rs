@@ -9,6 +9,8 @@ pub mod commands;
 pub mod error;
 pub mod extension;
 pub mod list;
+pub mod progress;
+pub mod threads;
 pub mod utils;
 
 use std::{env, path::PathBuf};
@@ -0,0 +1,175 @@
+//! Progress of long operations, printed to stderr.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use ubyte::ToByteUnit;
+
+use crate::{accessible::is_running_in_accessible_mode, info};
+
+/// Whether a progress line is drawn at the bottom of the terminal, see `clear_line`
+static LINE_IS_DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// Erase the progress line, if there is one, so other messages can be printed
+///
+/// The line is drawn again by the next update.
+pub fn clear_line() {
+    if LINE_IS_DRAWN.swap(false, Ordering::Relaxed) {
+        eprint!("\r\x1b[2K");
+    }
+}
+
+/// Counts the bytes processed by an operation and reports them with the rate and the time left
+///
+/// Clones share the same counters, so one `Progress` can follow files processed in parallel.
+/// Nothing is reported with `--quiet` or when stderr is not a terminal, and reports are printed as
+/// plain lines every few seconds in ACCESSIBLE mode, instead of a line updated in place.
+#[derive(Debug, Clone, Default)]
+pub struct Progress(Option<Arc<State>>);
+
+#[derive(Debug)]
+struct State {
+    /// What is being done, like "Compressing"
+    action: &'static str,
+    /// Bytes expected to be processed, if known
+    total: Option<u64>,
+    processed: AtomicU64,
+    start: Instant,
+    accessible: bool,
+    last_report: Mutex<Instant>,
+}
+
+impl Progress {
+    /// `total` gives the bytes expected to be processed, if known, it's only called when the progress
+    /// is reported, since finding it out can take a while, like walking the files to compress
+    pub fn new(action: &'static str, total: impl FnOnce() -> Option<u64>, quiet: bool) -> Self {
+        let accessible = is_running_in_accessible_mode();
+        if quiet || !accessible && atty::isnt(atty::Stream::Stderr) {
+            return Self(None);
+        }
+
+        let total = total();
+        let start = Instant::now();
+        Self(Some(Arc::new(State {
+            action,
+            total,
+            processed: AtomicU64::new(0),
+            start,
+            accessible,
+            last_report: Mutex::new(start),
+        })))
+    }
+
+    /// Count the bytes read from `reader`
+    pub fn reader<R>(&self, reader: R) -> ProgressReader<R> {
+        ProgressReader {
+            inner: reader,
+            progress: self.clone(),
+        }
+    }
+
+    /// Count `bytes` more processed bytes, reporting them if it's time to
+    pub fn add(&self, bytes: u64) {
+        let Some(state) = &self.0 else {
+            return;
+        };
+
+        let processed = state.processed.fetch_add(bytes, Ordering::Relaxed) + bytes;
+
+        // Other threads are already reporting
+        let Ok(mut last_report) = state.last_report.try_lock() else {
+            return;
+        };
+
+        let interval = if state.accessible {
+            Duration::from_secs(10)
+        } else {
+            Duration::from_millis(100)
+        };
+        if last_report.elapsed() < interval {
+            return;
+        }
+        *last_report = Instant::now();
+
+        let report = state.report(processed);
+        if state.accessible {
+            info!(accessible, "{}", report);
+        } else {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K{report}");
+            let _ = stderr.flush();
+            LINE_IS_DRAWN.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl State {
+    fn report(&self, processed: u64) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { processed as f64 / elapsed } else { 0.0 };
+        let rate_text = format!("{}/s", (rate as u64).bytes());
+
+        match self.total {
+            Some(total) if total > 0 => {
+                let percentage = (processed as f64 / total as f64 * 100.0).min(100.0);
+                let mut report = format!(
+                    "{} {} of {} ({:.0}%), {}",
+                    self.action,
+                    processed.bytes(),
+                    total.bytes(),
+                    percentage,
+                    rate_text
+                );
+                if rate > 0.0 && processed < total {
+                    let left = Duration::from_secs_f64((total - processed) as f64 / rate);
+                    report.push_str(&format!(", {} left", format_duration(left)));
+                }
+                report
+            }
+            _ => format!("{} {}, {}", self.action, processed.bytes(), rate_text),
+        }
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        clear_line();
+    }
+}
+
+/// Formats durations like "1h 02m", "3m 05s" or "12s"
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{seconds}s"),
+        (0, minutes, seconds) => format!("{minutes}m {seconds:02}s"),
+        (hours, minutes, _) => format!("{hours}h {minutes:02}m"),
+    }
+}
+
+/// See `Progress::reader`
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.progress.add(bytes as u64);
+        Ok(bytes)
+    }
+}
+
+/// Archives like zip are read out of order, seeking doesn't count as progress
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
@@ -0,0 +1,26 @@
+use std::{num::NonZeroUsize, thread};
+
+use once_cell::sync::OnceCell;
+
+/// How many threads compression, decompression and the files processed in parallel can use,
+/// set by `--threads`.
+pub static THREADS: OnceCell<usize> = OnceCell::new();
+
+/// One thread per CPU, used if `--threads` isn't given
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+pub fn threads() -> usize {
+    THREADS.get().copied().unwrap_or_else(available_threads)
+}
+
+/// Set the number of threads and size rayon's global pool with it
+pub fn set_threads(value: Option<NonZeroUsize>) {
+    let value = value.map_or_else(available_threads, NonZeroUsize::get);
+
+    if THREADS.set(value).is_ok() {
+        // This only fails if the pool is already running, in which case it keeps its size
+        let _ = rayon::ThreadPoolBuilder::new().num_threads(value).build_global();
+    }
+}
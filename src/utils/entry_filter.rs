@@ -0,0 +1,69 @@
+use std::path::{Component, Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::error::FinalError;
+
+/// Determines which archive entries should be extracted
+///
+/// Patterns can be exact paths (`bin/tool`) or globs (`docs/**/*.md`), an entry is
+/// selected if its path or any of its parent directories matches one of them.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    /// `None` if no patterns were given, in which case every entry is selected
+    patterns: Option<GlobSet>,
+}
+
+impl EntryFilter {
+    /// Build a filter out of the given patterns, an empty list selects every entry
+    pub fn new(patterns: &[String]) -> crate::Result<Self> {
+        if patterns.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let mut builder = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            // Entry paths are matched without their trailing slashes
+            let glob = GlobBuilder::new(pattern.trim_end_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|err| {
+                    FinalError::with_title(format!("Invalid entry pattern '{pattern}'")).detail(err.kind().to_string())
+                })?;
+            builder.add(glob);
+        }
+
+        let patterns = builder.build().map_err(|err| {
+            FinalError::with_title("Could not build the entry patterns").detail(err.kind().to_string())
+        })?;
+
+        Ok(Self {
+            patterns: Some(patterns),
+        })
+    }
+
+    /// Whether patterns were given, if not, every entry is selected
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_none()
+    }
+
+    /// Check if the entry at `path` (relative to the root of the archive) should be extracted
+    pub fn is_selected(&self, path: &Path) -> bool {
+        let Some(patterns) = &self.patterns else {
+            return true;
+        };
+
+        without_cur_dir(path)
+            .ancestors()
+            .take_while(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| patterns.is_match(ancestor))
+    }
+}
+
+/// Archives often store paths as "./dir/file", which should be treated as "dir/file"
+pub fn without_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+
+use super::{entry_filter::without_cur_dir, to_utf};
+use crate::error::{Error, FinalError};
+
+/// Maximum amount of similar entries suggested when the wanted one is not found
+const MAX_NEAR_MATCHES: usize = 5;
+
+/// Looks for a single archive entry by its path
+///
+/// The paths of the other entries are remembered while searching, so that the
+/// closest ones can be suggested if the wanted entry is not in the archive.
+#[derive(Debug)]
+pub struct EntryLookup {
+    wanted: PathBuf,
+    seen: Vec<PathBuf>,
+}
+
+impl EntryLookup {
+    pub fn new(wanted: &Path) -> Self {
+        Self {
+            wanted: without_cur_dir(wanted),
+            seen: vec![],
+        }
+    }
+
+    /// Check if the entry at `path` (relative to the root of the archive) is the wanted one
+    pub fn matches(&mut self, path: &Path) -> bool {
+        let path = without_cur_dir(path);
+
+        if path == self.wanted {
+            return true;
+        }
+
+        self.seen.push(path);
+        false
+    }
+
+    /// Error reported when no entry matched, suggesting the entries with the closest paths
+    pub fn not_found_error(self) -> Error {
+        let wanted = to_utf(&self.wanted);
+        let wanted_name = self.wanted.file_name();
+
+        let mut near_matches: Vec<(f64, &Path)> = self
+            .seen
+            .iter()
+            .map(|path| {
+                let similarity = strsim::normalized_levenshtein(&wanted, &to_utf(path));
+                // Entries with the same file name but in another directory are good suggestions too
+                let same_name = wanted_name.is_some() && path.file_name() == wanted_name;
+                (if same_name { 1.0 } else { similarity }, path.as_path())
+            })
+            .filter(|(similarity, _)| *similarity >= 0.6)
+            .collect();
+
+        near_matches.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        let mut error = FinalError::with_title(format!("Entry '{wanted}' not found in the archive"));
+
+        for (_, path) in near_matches.into_iter().take(MAX_NEAR_MATCHES) {
+            error = error.detail(format!("Did you mean '{}'?", to_utf(path)));
+        }
+
+        error
+            .hint("Use `ouch list` to see the paths of the entries in the archive")
+            .into()
+    }
+
+    /// Error reported when the wanted entry is a link to `target`, which has no content of its own to print
+    pub fn link_error(self, target: &Path) -> Error {
+        FinalError::with_title(format!(
+            "Entry '{}' is a link to '{}'",
+            to_utf(&self.wanted),
+            to_utf(target)
+        ))
+        .detail("Links have no content of their own to print")
+        .hint("Use `ouch cat` with the path of the entry it links to instead")
+        .into()
+    }
+}
@@ -1,5 +1,9 @@
 use std::path::Path;
 
+use ignore::overrides::{Override, OverrideBuilder};
+
+use crate::error::FinalError;
+
 /// Determines which files should be read or ignored during directory walking
 pub struct FileVisibilityPolicy {
     /// Enables reading .ignore files.
@@ -19,6 +23,17 @@ pub struct FileVisibilityPolicy {
 
     /// Enables reading `.git/info/exclude` files.
     pub read_git_exclude: bool,
+
+    /// Walks the entries of each directory sorted by file name, instead of in the order the OS lists them.
+    ///
+    /// Disabled by default.
+    pub sort_by_file_name: bool,
+
+    /// Globs given by `--include` and `--exclude`, the latter prefixed with `!`, they take precedence
+    /// over the other rules.
+    ///
+    /// Empty by default.
+    pub globs: Vec<String>,
 }
 
 impl Default for FileVisibilityPolicy {
@@ -28,6 +43,8 @@ impl Default for FileVisibilityPolicy {
             read_hidden: true,
             read_git_ignore: false,
             read_git_exclude: false,
+            sort_by_file_name: false,
+            globs: vec![],
         }
     }
 }
@@ -67,13 +84,71 @@ impl FileVisibilityPolicy {
         Self { read_hidden, ..self }
     }
 
+    #[must_use]
+    /// Walks the entries of each directory sorted by file name.
+    pub fn sort_by_file_name(self, sort_by_file_name: bool) -> Self {
+        Self {
+            sort_by_file_name,
+            ..self
+        }
+    }
+
+    /// Only keeps the files matching one of the `include` globs, if any, and leaves out the files and
+    /// directories matching one of the `exclude` globs.
+    ///
+    /// Globs follow the gitignore syntax and are matched against the paths relative to each directory
+    /// given to compress, so `target/` and `*.log` match at any depth, and `src/gen` only matches the
+    /// `gen` directory inside of `src`.
+    pub fn globs(self, include: &[String], exclude: &[String]) -> crate::Result<Self> {
+        // Excludes are added last so they win over includes
+        let globs: Vec<String> = include
+            .iter()
+            .map(String::from)
+            .chain(exclude.iter().map(|glob| format!("!{glob}")))
+            .collect();
+
+        // Checked here, so that building them again for each walked directory can't fail
+        build_overrides(Path::new("."), &globs)?;
+
+        Ok(Self { globs, ..self })
+    }
+
     /// Walks through a directory using [`ignore::Walk`]
     pub fn build_walker(&self, path: impl AsRef<Path>) -> ignore::Walk {
-        ignore::WalkBuilder::new(path)
+        let mut builder = ignore::WalkBuilder::new(&path);
+        builder
             .git_exclude(self.read_git_exclude)
             .git_ignore(self.read_git_ignore)
             .ignore(self.read_ignore)
-            .hidden(self.read_hidden)
-            .build()
+            .hidden(self.read_hidden);
+
+        if !self.globs.is_empty() {
+            let overrides = build_overrides(path.as_ref(), &self.globs).expect("globs are checked by `globs`");
+            builder.overrides(overrides);
+        }
+
+        if self.sort_by_file_name {
+            builder.sort_by_file_name(|a, b| a.cmp(b));
+        }
+
+        builder.build()
     }
 }
+
+/// Globs of `FileVisibilityPolicy::globs` matched relative to `root`, the path given to the walker,
+/// whose entries are yielded with it as a prefix
+fn build_overrides(root: &Path, globs: &[String]) -> crate::Result<Override> {
+    let mut builder = OverrideBuilder::new(root);
+
+    for glob in globs {
+        builder.add(glob).map_err(|err| {
+            FinalError::with_title(format!("Invalid glob '{}'", glob.trim_start_matches('!'))).detail(err.to_string())
+        })?;
+    }
+
+    let overrides = builder
+        .build()
+        .map_err(|err| FinalError::with_title("Could not build the globs").detail(err.to_string()))?;
+
+    Ok(overrides)
+}
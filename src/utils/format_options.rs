@@ -0,0 +1,158 @@
+use std::ops::RangeInclusive;
+
+use fs_err as fs;
+use once_cell::sync::OnceCell;
+use ubyte::ToByteUnit;
+
+use crate::{cli::parse_size, error::FinalError, extension::CompressionFormat};
+
+/// Options given with `--opt`, read by the encoders and decoders of their formats.
+static FORMAT_OPTIONS: OnceCell<FormatOptions> = OnceCell::new();
+
+pub fn format_options() -> &'static FormatOptions {
+    FORMAT_OPTIONS.get_or_init(FormatOptions::default)
+}
+
+pub fn set_format_options(options: FormatOptions) {
+    let _ = FORMAT_OPTIONS.set(options);
+}
+
+const SUPPORTED_OPTIONS: &str = "zstd.level, zstd.long, zstd.dict, xz.extreme, xz.dict-size, xz.block-size, \
+                                 lz4.block-size and brotli.window";
+
+/// Format specific settings that `--level` can't express, given as `--opt FORMAT.KEY=VALUE`
+#[derive(Debug, Default)]
+pub struct FormatOptions {
+    /// Compression level, unlike `--level` it can be negative for faster compression
+    pub zstd_level: Option<i32>,
+    /// Base 2 logarithm of the window size, enables long distance matching when compressing,
+    /// and raises the window size accepted when decompressing
+    pub zstd_long: Option<u32>,
+    /// Contents of a dictionary, which decompression needs too
+    pub zstd_dict: Option<Vec<u8>>,
+    /// Slower compression, for a slightly better ratio, like `xz -e`
+    pub xz_extreme: bool,
+    pub xz_dict_size: Option<u32>,
+    /// Size of the blocks compressed independently, makes the output seekable
+    pub xz_block_size: Option<u64>,
+    /// One of 64KiB, 256KiB, 1MiB or 4MiB
+    pub lz4_block_size: Option<u64>,
+    /// Base 2 logarithm of the window size
+    pub brotli_window: Option<u32>,
+    /// Options that were given, with their formats, like (Zstd, "zstd.long=27")
+    given: Vec<(CompressionFormat, String)>,
+}
+
+impl FormatOptions {
+    pub fn new(options: &[String]) -> crate::Result<Self> {
+        let mut parsed = Self::default();
+
+        for option in options {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| invalid_option(option, "Options are written as FORMAT.KEY=VALUE"))?;
+
+            let Some((format, name)) = key.split_once('.') else {
+                return Err(unknown_option(option).into());
+            };
+            let format = match format {
+                "zst" | "zstd" => CompressionFormat::Zstd,
+                "xz" | "lzma" => CompressionFormat::Lzma,
+                "lz4" => CompressionFormat::Lz4,
+                "br" | "brotli" => CompressionFormat::Brotli,
+                _ => return Err(unknown_option(option).into()),
+            };
+
+            match name {
+                "level" if format == CompressionFormat::Zstd => {
+                    let levels = zstd::zstd_safe::min_c_level()..=zstd::zstd_safe::max_c_level();
+                    parsed.zstd_level = Some(parse_in_range(option, value, levels)?);
+                }
+                "long" if format == CompressionFormat::Zstd => {
+                    parsed.zstd_long = Some(parse_in_range(option, value, 10..=31)?);
+                }
+                "dict" if format == CompressionFormat::Zstd => {
+                    let dictionary = fs::read(value).map_err(|err| {
+                        FinalError::with_title(format!("Could not read the dictionary of '{option}'"))
+                            .detail(err.to_string())
+                    })?;
+                    parsed.zstd_dict = Some(dictionary);
+                }
+                "extreme" if format == CompressionFormat::Lzma => {
+                    parsed.xz_extreme = value
+                        .parse()
+                        .map_err(|_| invalid_option(option, "Expected true or false"))?;
+                }
+                "dict-size" if format == CompressionFormat::Lzma => {
+                    let size = parse_size_in_range(option, value, 4 << 10..=1536 << 20)?;
+                    parsed.xz_dict_size = Some(size as u32);
+                }
+                "block-size" if format == CompressionFormat::Lzma => {
+                    let size = parse_size(value).map_err(|err| invalid_option(option, err))?;
+                    if size == 0 {
+                        return Err(invalid_option(option, "Expected a size larger than 0").into());
+                    }
+                    parsed.xz_block_size = Some(size);
+                }
+                "block-size" if format == CompressionFormat::Lz4 => {
+                    let size = parse_size(value).map_err(|err| invalid_option(option, err))?;
+                    if ![64 << 10, 256 << 10, 1 << 20, 4 << 20].contains(&size) {
+                        return Err(invalid_option(option, "Expected 64K, 256K, 1M or 4M").into());
+                    }
+                    parsed.lz4_block_size = Some(size);
+                }
+                "window" if format == CompressionFormat::Brotli => {
+                    parsed.brotli_window = Some(parse_in_range(option, value, 10..=24)?);
+                }
+                _ => return Err(unknown_option(option).into()),
+            }
+
+            parsed.given.push((format, option.clone()));
+        }
+
+        Ok(parsed)
+    }
+
+    /// Options given for formats that aren't in `formats`
+    pub fn unused_by<'a>(&'a self, formats: &'a [CompressionFormat]) -> impl Iterator<Item = &'a str> {
+        self.given
+            .iter()
+            .filter(|(format, _)| !formats.contains(format))
+            .map(|(_, option)| option.as_str())
+    }
+}
+
+fn parse_in_range<T>(option: &str, value: &str, range: RangeInclusive<T>) -> crate::Result<T>
+where
+    T: std::str::FromStr + PartialOrd + std::fmt::Display,
+{
+    value.parse().ok().filter(|value| range.contains(value)).ok_or_else(|| {
+        let expected = format!("Expected a number from {} to {}", range.start(), range.end());
+        invalid_option(option, expected).into()
+    })
+}
+
+fn parse_size_in_range(option: &str, value: &str, range: RangeInclusive<u64>) -> crate::Result<u64> {
+    let size = parse_size(value).map_err(|err| invalid_option(option, err))?;
+
+    if !range.contains(&size) {
+        let expected = format!(
+            "Expected a size from {} to {}",
+            range.start().bytes(),
+            range.end().bytes()
+        );
+        return Err(invalid_option(option, expected).into());
+    }
+
+    Ok(size)
+}
+
+fn invalid_option(option: &str, expected: impl ToString) -> FinalError {
+    FinalError::with_title(format!("Invalid option '{option}'")).detail(expected.to_string())
+}
+
+fn unknown_option(option: &str) -> FinalError {
+    FinalError::with_title(format!("Unknown option '{option}'"))
+        .detail(format!("Supported options are {SUPPORTED_OPTIONS}"))
+        .hint("Example: --opt zstd.long=27")
+}
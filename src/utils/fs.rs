@@ -2,7 +2,7 @@
 
 use std::{
     env,
-    io::Read,
+    io::{self, BufRead, Read},
     path::{Path, PathBuf},
 };
 
@@ -57,6 +57,20 @@ pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
     Ok(previous_location)
 }
 
+/// Returns true if `path` is `-`, used to read from stdin or write to stdout instead of a file
+pub fn is_stdio_path(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Opens the file at `path` for reading, or stdin if `path` is `-`
+pub fn open_file_or_stdin(path: &Path) -> crate::Result<Box<dyn Read + Send>> {
+    if is_stdio_path(path) {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+}
+
 /// Try to detect the file extension by looking for known magic strings
 /// Source: <https://en.wikipedia.org/wiki/List_of_file_signatures>
 pub fn try_infer_extension(path: &Path) -> Option<Extension> {
@@ -86,12 +100,29 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
     fn is_zst(buf: &[u8]) -> bool {
         buf.starts_with(&[0x28, 0xB5, 0x2F, 0xFD])
     }
+    fn is_sevenz(buf: &[u8]) -> bool {
+        buf.starts_with(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C])
+    }
+    fn is_rar(buf: &[u8]) -> bool {
+        // ref https://www.rarlab.com/technote.htm#rarsign
+        // RAR 4.x: 52 61 72 21 1A 07 00
+        // RAR 5.x: 52 61 72 21 1A 07 01 00
+        buf.starts_with(&[0x52, 0x61, 0x72, 0x21, 0x1A, 0x07]) && (buf[6] == 0x00 || buf[6..=7] == [0x01, 0x00])
+    }
 
     let buf = {
         let mut buf = [0; 270];
 
-        // Error cause will be ignored, so use std::fs instead of fs_err
-        let result = std::fs::File::open(path).map(|mut file| file.read(&mut buf));
+        let result = if is_stdio_path(path) {
+            // Peek into the stdin buffer without consuming it, so the bytes can still be decompressed later
+            io::stdin().lock().fill_buf().map(|bytes| {
+                let len = bytes.len().min(buf.len());
+                buf[..len].copy_from_slice(&bytes[..len]);
+            })
+        } else {
+            // Error cause will be ignored, so use std::fs instead of fs_err
+            std::fs::File::open(path).and_then(|mut file| file.read(&mut buf).map(drop))
+        };
 
         // In case of file open or read failure, could not infer a extension
         if result.is_err() {
@@ -117,6 +148,10 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
         Some(Extension::new(&[Snappy], "sz"))
     } else if is_zst(&buf) {
         Some(Extension::new(&[Zstd], "zst"))
+    } else if is_sevenz(&buf) {
+        Some(Extension::new(&[SevenZip], "7z"))
+    } else if is_rar(&buf) {
+        Some(Extension::new(&[Rar], "rar"))
     } else {
         None
     }
@@ -0,0 +1,206 @@
+use std::{
+    error, fmt,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use ubyte::ToByteUnit;
+
+/// Compression ratios are only checked after this many bytes are decompressed, small files
+/// can have huge ratios (an empty line repeated a thousand times) without being a threat
+const RATIO_GRACE_BYTES: u64 = 1024 * 1024;
+
+/// Limits on what can be written by a single decompression, to protect against decompression bombs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecompressionLimits {
+    /// Maximum number of decompressed bytes
+    pub max_size: Option<u64>,
+    /// Maximum number of archive entries extracted
+    pub max_entries: Option<u64>,
+    /// Maximum ratio between the decompressed and compressed sizes
+    pub max_ratio: Option<u32>,
+}
+
+impl DecompressionLimits {
+    /// Whether no limit was set
+    pub fn is_unlimited(&self) -> bool {
+        self.max_size.is_none() && self.max_entries.is_none() && self.max_ratio.is_none()
+    }
+}
+
+/// The limit that was exceeded, carried inside of `io::Error`s so it can cross `Read` implementations,
+/// and converted into `Error::LimitExceeded` by `From<io::Error>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Size(u64),
+    Entries(u64),
+    Ratio(u32),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Size(max_size) => write!(f, "More than {} would be decompressed", max_size.bytes()),
+            Self::Entries(max_entries) => write!(f, "More than {max_entries} entries would be extracted"),
+            Self::Ratio(max_ratio) => write!(f, "The data expands more than {max_ratio} times its compressed size"),
+        }
+    }
+}
+
+impl error::Error for LimitExceeded {}
+
+impl From<LimitExceeded> for io::Error {
+    fn from(limit: LimitExceeded) -> Self {
+        io::Error::other(limit)
+    }
+}
+
+impl LimitExceeded {
+    /// Look for a `LimitExceeded` in the chain of errors, libraries like tar wrap the errors of our readers
+    pub fn find_in(err: &(dyn error::Error + 'static)) -> Option<Self> {
+        if let Some(limit) = err.downcast_ref::<Self>() {
+            return Some(*limit);
+        }
+
+        // `io::Error::source` skips the error it wraps, so it has to be unwrapped by hand
+        if let Some(inner) = err.downcast_ref::<io::Error>().and_then(io::Error::get_ref) {
+            return Self::find_in(inner);
+        }
+
+        err.source().and_then(Self::find_in)
+    }
+}
+
+/// Counts the bytes read and written by a decompression and checks them against `DecompressionLimits`
+///
+/// Clones share the same counters, so the compressed input can be counted at the start of a
+/// decoder chain while the decompressed output is counted at the end of it.
+#[derive(Debug, Clone, Default)]
+pub struct LimitTracker {
+    limits: DecompressionLimits,
+    input_bytes: Arc<AtomicU64>,
+    output_bytes: Arc<AtomicU64>,
+    entries: Arc<AtomicU64>,
+}
+
+impl LimitTracker {
+    pub fn new(limits: DecompressionLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Count the compressed bytes read from `reader`
+    pub fn count_input<R: Read>(&self, reader: R) -> InputCounter<R> {
+        InputCounter {
+            inner: reader,
+            input_bytes: Arc::clone(&self.input_bytes),
+        }
+    }
+
+    /// Count the decompressed bytes read from `reader`, failing once a limit is exceeded
+    ///
+    /// The ratio is computed against all the input counted by `count_input`.
+    pub fn limit_output<R: Read>(&self, reader: R) -> LimitedReader<R> {
+        LimitedReader {
+            inner: reader,
+            tracker: self.clone(),
+            compressed_size: None,
+            output_bytes: 0,
+        }
+    }
+
+    /// Like `limit_output`, for a single entry whose compressed size is known, like the entries of zip archives
+    ///
+    /// The ratio is computed for this entry alone.
+    pub fn limit_entry_output<R: Read>(&self, reader: R, compressed_size: u64) -> LimitedReader<R> {
+        LimitedReader {
+            compressed_size: Some(compressed_size),
+            ..self.limit_output(reader)
+        }
+    }
+
+    /// Like `limit_output`, for the decompressed bytes of an archive whose entries are limited by
+    /// `limit_entry_output`, like a zip archive read through other formats
+    ///
+    /// They are checked against the limits without being added to the extracted bytes, so that what is
+    /// read or stored before the entries can't exceed them either.
+    pub fn limit_archive<R: Read>(&self, reader: R) -> LimitedReader<R> {
+        LimitedReader {
+            inner: reader,
+            tracker: Self {
+                output_bytes: Arc::default(),
+                ..self.clone()
+            },
+            compressed_size: None,
+            output_bytes: 0,
+        }
+    }
+
+    /// Count one more extracted entry
+    pub fn add_entry(&self) -> crate::Result<()> {
+        let entries = self.entries.fetch_add(1, Ordering::Relaxed) + 1;
+
+        match self.limits.max_entries {
+            Some(max_entries) if entries > max_entries => Err(LimitExceeded::Entries(max_entries).into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_output(&self, total_output: u64, output: u64, compressed_size: Option<u64>) -> Result<(), LimitExceeded> {
+        if let Some(max_size) = self.limits.max_size {
+            if total_output > max_size {
+                return Err(LimitExceeded::Size(max_size));
+            }
+        }
+
+        if let Some(max_ratio) = self.limits.max_ratio {
+            let input = compressed_size.unwrap_or_else(|| self.input_bytes.load(Ordering::Relaxed));
+            if output > RATIO_GRACE_BYTES && output as f64 > input.max(1) as f64 * f64::from(max_ratio) {
+                return Err(LimitExceeded::Ratio(max_ratio));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// See `LimitTracker::count_input`
+pub struct InputCounter<R> {
+    inner: R,
+    input_bytes: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for InputCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.input_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        Ok(bytes)
+    }
+}
+
+/// See `LimitTracker::limit_output`
+pub struct LimitedReader<R> {
+    inner: R,
+    tracker: LimitTracker,
+    compressed_size: Option<u64>,
+    /// Bytes read through this reader, the tracker holds the total of all readers
+    output_bytes: u64,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.output_bytes += bytes as u64;
+        let total_output = self.tracker.output_bytes.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+
+        self.tracker
+            .check_output(total_output, self.output_bytes, self.compressed_size)?;
+
+        Ok(bytes)
+    }
+}
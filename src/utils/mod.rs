@@ -4,19 +4,30 @@
 //! stdin interaction helpers.
 
 pub mod colors;
+mod entry_filter;
+mod entry_lookup;
 mod file_visibility;
+mod format_options;
 mod formatting;
 mod fs;
+mod limits;
 mod question;
+mod reproducible;
 
+pub use entry_filter::{without_cur_dir, EntryFilter};
+pub use entry_lookup::EntryLookup;
 pub use file_visibility::FileVisibilityPolicy;
+pub use format_options::{format_options, set_format_options, FormatOptions};
 pub use formatting::{nice_directory_display, pretty_format_list_of_paths, strip_cur_dir, to_utf, EscapedPathDisplay};
 pub use fs::{
-    cd_into_same_dir_as, clear_path, create_dir_if_non_existent, is_symlink, remove_file_or_dir, try_infer_extension,
+    cd_into_same_dir_as, clear_path, create_dir_if_non_existent, is_stdio_path, is_symlink, open_file_or_stdin,
+    remove_file_or_dir, try_infer_extension,
 };
+pub use limits::{DecompressionLimits, LimitExceeded, LimitTracker};
 pub use question::{
     ask_to_create_file, user_wants_to_continue, user_wants_to_overwrite, QuestionAction, QuestionPolicy,
 };
+pub use reproducible::Reproducible;
 pub use utf8::{get_invalid_utf8_paths, is_invalid_utf8};
 
 mod utf8 {
@@ -121,6 +121,8 @@ impl<'a> Confirmation<'a> {
             (Some(placeholder), Some(subs)) => Cow::Owned(self.prompt.replace(placeholder, subs)),
         };
 
+        crate::progress::clear_line();
+
         // Ask the same question to end while no valid answers are given
         loop {
             if is_running_in_accessible_mode() {
@@ -0,0 +1,52 @@
+use std::env;
+
+use crate::warning;
+
+/// Makes archives depend only on the paths and contents of the archived files, see `--reproducible`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reproducible {
+    /// Modification times later than this Unix timestamp are replaced by it, taken from `SOURCE_DATE_EPOCH`
+    pub source_date_epoch: u64,
+}
+
+impl Reproducible {
+    /// Clamp modification times to `source_date_epoch`, or to `SOURCE_DATE_EPOCH` if not given,
+    /// every modification time is set to 0 if neither is
+    pub fn new(source_date_epoch: Option<u64>) -> Self {
+        Self {
+            source_date_epoch: source_date_epoch
+                .or_else(source_date_epoch_from_env)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Modification time stored for a file modified at `mtime`
+    pub fn mtime(&self, mtime: u64) -> u64 {
+        mtime.min(self.source_date_epoch)
+    }
+
+    /// Permissions stored for a file with `mode`, only keeping whether it is executable
+    pub fn mode(&self, mode: u32, is_dir: bool) -> u32 {
+        if is_dir || mode & 0o111 != 0 {
+            0o755
+        } else {
+            0o644
+        }
+    }
+}
+
+/// The `SOURCE_DATE_EPOCH` environment variable, only read with `--reproducible`, so that an invalid value
+/// meant for another tool doesn't break compression
+fn source_date_epoch_from_env() -> Option<u64> {
+    let value = env::var_os("SOURCE_DATE_EPOCH").filter(|value| !value.is_empty())?;
+
+    let timestamp = value.to_str().and_then(|value| value.parse().ok());
+    if timestamp.is_none() {
+        warning!(
+            "Ignoring SOURCE_DATE_EPOCH '{}', it's not a Unix timestamp.",
+            value.to_string_lossy()
+        );
+    }
+
+    timestamp
+}
@@ -1,7 +1,11 @@
 #[macro_use]
 mod utils;
 
-use std::{iter::once, path::PathBuf};
+use std::{
+    io::{Read, Write},
+    iter::once,
+    path::{Path, PathBuf},
+};
 
 use fs_err as fs;
 use parse_display::Display;
@@ -10,7 +14,7 @@ use rand::{rngs::SmallRng, Rng, SeedableRng};
 use tempfile::tempdir;
 use test_strategy::{proptest, Arbitrary};
 
-use crate::utils::{assert_same_directory, write_random_content};
+use crate::utils::{assert_same_directory, cargo_bin, write_random_content};
 
 // tar and zip extensions
 #[derive(Arbitrary, Debug, Display)]
@@ -25,7 +29,10 @@ enum DirectoryExtension {
     Tsz,
     Txz,
     Tzst,
+    Tbr,
     Zip,
+    #[display("7z")]
+    SevenZ,
 }
 
 // extensions of single file compression formats
@@ -40,6 +47,7 @@ enum FileExtension {
     Sz,
     Xz,
     Zst,
+    Br,
 }
 
 #[derive(Arbitrary, Debug, Display)]
@@ -79,6 +87,42 @@ fn create_random_files(dir: impl Into<PathBuf>, depth: u8, rng: &mut SmallRng) {
     }
 }
 
+// create the files given as (path, contents) under the specified directory, with their parent directories
+fn create_files(dir: impl Into<PathBuf>, files: impl IntoIterator<Item = (impl AsRef<Path>, impl AsRef<[u8]>)>) {
+    let dir = &dir.into();
+
+    for (path, contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+// compress `input` into `archive` and decompress it into `output`, with `args` given to both commands, returning
+// what they printed to stderr
+fn round_trip(args: &[&str], input: &Path, archive: &Path, output: &Path) -> String {
+    let compressed = cargo_bin()
+        .args(args)
+        .args(["--yes", "c"])
+        .arg(input)
+        .arg(archive)
+        .assert()
+        .success();
+    let decompressed = cargo_bin()
+        .args(args)
+        .args(["--yes", "d"])
+        .arg(archive)
+        .arg("--dir")
+        .arg(output)
+        .assert()
+        .success();
+
+    [compressed, decompressed]
+        .iter()
+        .map(|assert| String::from_utf8_lossy(&assert.get_output().stderr).into_owned())
+        .collect()
+}
+
 // compress and decompress a single empty file
 #[proptest(cases = 512)]
 fn single_empty_file(ext: Extension, #[any(size_range(0..8).lift())] exts: Vec<FileExtension>) {
@@ -139,5 +183,1252 @@ fn multiple_files(
     create_random_files(before_dir, depth, &mut SmallRng::from_entropy());
     ouch!("-A", "c", before_dir, archive);
     ouch!("-A", "d", archive, "-d", after);
-    assert_same_directory(before, after, !matches!(ext, DirectoryExtension::Zip));
+    assert_same_directory(
+        before,
+        after,
+        !matches!(ext, DirectoryExtension::Zip | DirectoryExtension::SevenZ),
+    );
+}
+
+// decompress files made of multiple concatenated members/streams, like `cat a.gz b.gz > c.gz`
+#[test]
+fn concatenated_streams() {
+    for ext in ["gz", "bz2", "xz", "zst"] {
+        let dir = tempdir().unwrap();
+        let dir = dir.path();
+        let archive = &dir.join(format!("file.{ext}"));
+        let after = &dir.join("after");
+        let mut expected = Vec::new();
+        let mut concatenated = Vec::new();
+        for i in 0..3 {
+            let part = &dir.join(format!("part{i}"));
+            let part_archive = &dir.join(format!("part{i}.{ext}"));
+            write_random_content(&mut fs::File::create(part).unwrap(), &mut SmallRng::from_entropy());
+            ouch!("-A", "c", part, part_archive);
+            expected.extend(fs::read(part).unwrap());
+            concatenated.extend(fs::read(part_archive).unwrap());
+        }
+        fs::write(archive, concatenated).unwrap();
+        ouch!("-A", "d", archive, "-d", after);
+        assert_eq!(fs::read(after.join("file")).unwrap(), expected);
+    }
+}
+
+// test the integrity of valid and corrupted archives without extracting them
+#[test]
+fn test_integrity() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before_file = &dir.join("file");
+    let mut data = vec![0; 4096];
+    SmallRng::from_entropy().fill(&mut data[..]);
+    fs::write(before_file, data).unwrap();
+
+    for format in ["tar.gz", "zip", "7z", "tar.zst", "xz"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        ouch!("-A", "c", before_file, archive);
+        ouch!("-A", "t", archive);
+        assert!(!dir.join("archive").exists());
+    }
+
+    // a truncated stream
+    let truncated = &dir.join("truncated.tar.gz");
+    let mut bytes = fs::read(dir.join("archive.tar.gz")).unwrap();
+    bytes.truncate(bytes.len() / 2);
+    fs::write(truncated, bytes).unwrap();
+    cargo_bin().args(["-A", "t"]).arg(truncated).assert().failure();
+
+    // a zip entry whose content doesn't match its CRC32
+    let corrupted = &dir.join("corrupted.zip");
+    let mut bytes = fs::read(dir.join("archive.zip")).unwrap();
+    bytes[1000] ^= 0xFF;
+    fs::write(corrupted, bytes).unwrap();
+    cargo_bin().args(["-A", "t"]).arg(corrupted).assert().failure();
+
+    // a wrapped 7z archive that the user declines to load in memory isn't counted as passed
+    let wrapped = &dir.join("archive.7z.gz");
+    ouch!("-A", "c", before_file, wrapped);
+    let output = cargo_bin()
+        .args(["-A", "--no", "t"])
+        .args([&dir.join("archive.zip"), wrapped])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 of 2 files passed"), "{stderr}");
+    assert!(stderr.contains("archive.7z.gz"), "{stderr}");
+}
+
+// rar archives can only be decompressed, so compressing to .rar must fail without creating the output
+#[test]
+fn cannot_compress_to_rar() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before_file = &dir.join("file");
+    let archive = &dir.join("archive.rar");
+    fs::write(before_file, "hello").unwrap();
+    cargo_bin()
+        .args(["-A", "c"])
+        .arg(before_file)
+        .arg(archive)
+        .assert()
+        .failure();
+    assert!(!archive.exists());
+}
+
+// the long listing shows the metadata of each entry, and the totals at the end
+#[test]
+fn list_long() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("file", "a".repeat(1000))]);
+
+    for ext in ["tar", "zip"] {
+        let archive = &dir.join(format!("archive.{ext}"));
+        ouch!("-A", "c", before, archive);
+
+        let output = cargo_bin()
+            .args(["-A", "--yes", "list", "--long"])
+            .arg(archive)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        let file_line = stdout.lines().find(|line| line.ends_with("before/file")).unwrap();
+        assert!(file_line.starts_with("-rw"));
+        assert!(file_line.contains(" 1000 "));
+        assert!(stdout.contains("2 entries, 1000 bytes"));
+        assert_eq!(ext == "zip", stdout.contains("bytes compressed"));
+    }
+}
+
+// JSON output carries the format chain of each archive and the metadata of its entries
+#[test]
+fn list_json() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("file", "hello")]);
+    let tar_gz = &dir.join("archive.tar.gz");
+    let zip = &dir.join("archive.zip");
+    ouch!("-A", "c", before, tar_gz);
+    ouch!("-A", "c", before, zip);
+
+    let output = cargo_bin()
+        .args(["-A", "--yes", "list", "--output", "json"])
+        .args([tar_gz, zip])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let archives: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(archives[0]["formats"], serde_json::json!(["tar", "gz"]));
+    assert_eq!(archives[1]["formats"], serde_json::json!(["zip"]));
+    for archive in archives.as_array().unwrap() {
+        let entries = archive["entries"].as_array().unwrap();
+        let file = entries.iter().find(|entry| entry["path"] == "before/file").unwrap();
+        assert_eq!(file["type"], "file");
+        assert_eq!(file["size"], 5);
+    }
+
+    let output = cargo_bin()
+        .args(["-A", "--yes", "list", "--output", "ndjson"])
+        .arg(zip)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let lines: Vec<serde_json::Value> = output
+        .stdout
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+
+    assert_eq!(lines[0]["formats"], serde_json::json!(["zip"]));
+    assert!(lines[1..].iter().any(|line| line["path"] == "before/file"));
+}
+
+// decompression stops when a limit is exceeded, leaving nothing behind
+#[test]
+fn decompression_limits() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(
+        before,
+        [("zeros", vec![0; 4 * 1024 * 1024]), ("other", b"other".to_vec())],
+    );
+    let out = &dir.join("out");
+    fs::create_dir(out).unwrap();
+
+    let zip = &dir.join("archive.zip");
+    let tar = &dir.join("archive.tar");
+    let zst = &dir.join("zeros.zst");
+    ouch!("-A", "c", before, zip);
+    ouch!("-A", "c", before, tar);
+    ouch!("-A", "c", before.join("zeros"), zst);
+
+    for (archive, limit) in [
+        (zip, ["--max-ratio", "100"]),
+        (zip, ["--max-entries", "2"]),
+        (tar, ["--max-size", "1M"]),
+        (tar, ["--max-entries", "2"]),
+        (zst, ["--max-size", "1M"]),
+        (zst, ["--max-ratio", "100"]),
+    ] {
+        cargo_bin()
+            .args(["-A", "--yes", "d"])
+            .arg(archive)
+            .arg("-d")
+            .arg(out)
+            .args(limit)
+            .assert()
+            .failure();
+        assert_eq!(fs::read_dir(out).unwrap().count(), 0);
+    }
+
+    // limits that aren't exceeded don't get in the way
+    cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(zip)
+        .arg("-d")
+        .arg(out)
+        .args(["--max-size", "5M", "--max-entries", "3"])
+        .assert()
+        .success();
+    assert_same_directory(before, out.join("before"), false);
+}
+
+// hostile archives can't write or link outside of the output directory through symlinks
+#[cfg(unix)]
+#[test]
+fn hostile_symlinks() {
+    fn symlink(builder: &mut tar::Builder<fs::File>, path: &str, target: &str) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_link_name(target).unwrap();
+        builder.append_data(&mut header, path, std::io::empty()).unwrap();
+    }
+
+    fn file(builder: &mut tar::Builder<fs::File>, path: &str) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, "hello".as_bytes()).unwrap();
+    }
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let outside = &dir.join("outside");
+    fs::create_dir(outside).unwrap();
+    let out = &dir.join("out");
+    fs::create_dir(out).unwrap();
+
+    let archive = &dir.join("hostile.tar");
+    let mut builder = tar::Builder::new(fs::File::create(archive).unwrap());
+    symlink(&mut builder, "absolute", outside.to_str().unwrap());
+    file(&mut builder, "absolute/passwd");
+    symlink(&mut builder, "relative", "../outside");
+    file(&mut builder, "relative/passwd");
+    // each symlink is inside on its own, but together they escape
+    symlink(&mut builder, "chained", "self/..");
+    symlink(&mut builder, "self", ".");
+    symlink(&mut builder, "fine", "file");
+    file(&mut builder, "file");
+    builder.into_inner().unwrap();
+
+    let output = cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(out)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping"));
+
+    assert_eq!(fs::read_dir(outside).unwrap().count(), 0);
+    let extracted = &out.join("hostile");
+    assert!(fs::symlink_metadata(extracted.join("chained")).is_err());
+    assert!(!extracted.join("relative").is_symlink());
+    assert!(!extracted.join("absolute").is_symlink());
+    assert_eq!(fs::read_link(extracted.join("fine")).unwrap(), PathBuf::from("file"));
+    assert_eq!(fs::read_to_string(extracted.join("fine")).unwrap(), "hello");
+
+    // zip archives don't go through symlinks, but their paths can still try to leave the output directory
+    let archive = &dir.join("hostile.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(archive).unwrap());
+    writer.start_file("../escaped", Default::default()).unwrap();
+    writer.start_file("/absolute", Default::default()).unwrap();
+    writer.start_file("file", Default::default()).unwrap();
+    writer.finish().unwrap();
+
+    let output = cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(out)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unsafe path"));
+    assert!(!dir.join("escaped").exists());
+    assert!(out.join("file").exists());
+}
+
+// zip archives store symlinks as links, unless asked to follow them
+#[cfg(unix)]
+#[test]
+fn zip_symlinks() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("lib/tool.js", "tool")]);
+    std::os::unix::fs::symlink("lib/tool.js", before.join("tool")).unwrap();
+    std::os::unix::fs::symlink("lib", before.join("libs")).unwrap();
+
+    let out = &dir.join("out");
+    round_trip(&["-A"], before, &dir.join("links.zip"), out);
+    let after = &out.join("before");
+    assert_eq!(fs::read_link(after.join("tool")).unwrap(), PathBuf::from("lib/tool.js"));
+    assert_eq!(fs::read_link(after.join("libs")).unwrap(), PathBuf::from("lib"));
+    assert_eq!(fs::read_to_string(after.join("tool")).unwrap(), "tool");
+
+    let archive = &dir.join("followed.zip");
+    ouch!("-A", "c", before, archive, "--follow-symlinks");
+    let out = &dir.join("followed");
+    ouch!("-A", "d", archive, "-d", out);
+    let after = &out.join("before");
+    assert!(!after.join("tool").is_symlink());
+    assert_eq!(fs::read_to_string(after.join("tool")).unwrap(), "tool");
+
+    // links that lead outside of the output directory are skipped
+    let archive = &dir.join("hostile.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(archive).unwrap());
+    writer.add_symlink("evil", "/etc", Default::default()).unwrap();
+    writer.add_symlink("up", "../..", Default::default()).unwrap();
+    writer.start_file("file", Default::default()).unwrap();
+    writer.finish().unwrap();
+    let out = &dir.join("hostile");
+    ouch!("-A", "d", archive, "-d", out);
+    assert!(fs::symlink_metadata(out.join("evil")).is_err());
+    assert!(fs::symlink_metadata(out.join("up")).is_err());
+    assert!(out.join("file").exists());
+
+    // link targets are bounded, instead of being read into memory whatever their declared size
+    let archive = &dir.join("huge-link.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(archive).unwrap());
+    writer
+        .add_symlink("huge", "x".repeat(1 << 20), Default::default())
+        .unwrap();
+    writer.finish().unwrap();
+    for command in ["d", "l"] {
+        let output = cargo_bin()
+            .args(["-A", "--yes", command])
+            .arg(archive)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("longer than 4096 bytes"));
+    }
+}
+
+// tar archives store symlinks and hardlinks as links, unless they are followed
+#[cfg(unix)]
+#[test]
+fn tar_links() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("data", "data")]);
+    fs::hard_link(before.join("data"), before.join("copy")).unwrap();
+    std::os::unix::fs::symlink("data", before.join("link")).unwrap();
+
+    let out = &dir.join("out");
+    round_trip(&["-A"], before, &dir.join("links.tar"), out);
+    let after = &out.join("before");
+    assert_eq!(fs::read_link(after.join("link")).unwrap(), PathBuf::from("data"));
+    assert_eq!(
+        fs::metadata(after.join("data")).unwrap().ino(),
+        fs::metadata(after.join("copy")).unwrap().ino()
+    );
+    assert_eq!(fs::read_to_string(after.join("copy")).unwrap(), "data");
+
+    let archive = &dir.join("followed.tar");
+    ouch!("-A", "c", before, archive, "--dereference");
+    let out = &dir.join("followed");
+    ouch!("-A", "d", archive, "-d", out);
+    let after = &out.join("before");
+    assert!(!after.join("link").is_symlink());
+    assert_eq!(fs::read_to_string(after.join("link")).unwrap(), "data");
+    assert_ne!(
+        fs::metadata(after.join("data")).unwrap().ino(),
+        fs::metadata(after.join("copy")).unwrap().ino()
+    );
+}
+
+// archives created with --reproducible only depend on the paths and contents of the files
+#[test]
+fn reproducible_archives() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let files = ["b/two", "a/one", "c"];
+    // directories list their entries in different orders depending on how they were created
+    create_files(dir.join("first/input"), files.map(|file| (file, file)));
+    create_files(
+        dir.join("second/input"),
+        files.into_iter().rev().map(|file| (file, file)),
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir.join("second/input/c"), std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+
+    for ext in ["tar", "tar.gz", "tar.zst", "zip"] {
+        // the output of the multithreaded compressors doesn't depend on the number of threads either
+        let [first, second, single_threaded] =
+            [("first", None), ("second", None), ("first", Some("1"))].map(|(tree, threads)| {
+                let archive = dir.join(format!("{tree}-{}.{ext}", threads.unwrap_or("default")));
+                cargo_bin()
+                    .args(["-A", "--yes", "c", "--reproducible"])
+                    .args(threads.map(|threads| ["--threads", threads]).into_iter().flatten())
+                    .arg(dir.join(tree).join("input"))
+                    .arg(&archive)
+                    .env("SOURCE_DATE_EPOCH", "1000000000")
+                    .assert()
+                    .success();
+                fs::read(archive).unwrap()
+            });
+        assert!(first == second, "{ext} archives differ");
+        assert!(first == single_threaded, "{ext} archives differ with --threads 1");
+    }
+
+    // the gzip header has no modification time nor file name
+    let gzip = fs::read(dir.join("first-default.tar.gz")).unwrap();
+    assert_eq!(gzip[3] & 0x08, 0);
+    assert_eq!(gzip[4..8], [0; 4]);
+
+    let mut archive = tar::Archive::new(fs::File::open(dir.join("first-default.tar")).unwrap());
+    for entry in archive.entries().unwrap() {
+        let header = entry.unwrap().header().clone();
+        assert_eq!(header.mtime().unwrap(), 1000000000);
+        assert_eq!(header.uid().unwrap(), 0);
+        assert_eq!(header.gid().unwrap(), 0);
+    }
+
+    // SOURCE_DATE_EPOCH is only read with --reproducible, and an invalid value is ignored with a warning
+    for (args, warns) in [(vec!["c"], false), (vec!["c", "--reproducible"], true)] {
+        let archive = dir.join("invalid-epoch.tar");
+        let output = cargo_bin()
+            .args(["-A", "--yes"])
+            .args(args)
+            .arg(dir.join("first/input"))
+            .arg(&archive)
+            .env("SOURCE_DATE_EPOCH", "abc")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr).contains("SOURCE_DATE_EPOCH"),
+            warns
+        );
+    }
+}
+
+// xz and zstd compressed with several threads decompress to the input, and don't depend on the number of threads
+#[test]
+fn multithreaded_compression() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let mut rng = SmallRng::from_entropy();
+    let mut data = vec![0; 1 << 22];
+    rng.fill(&mut data[..1 << 20]);
+    fs::write(dir.join("input"), &data).unwrap();
+
+    for ext in ["xz", "zst"] {
+        for threads in ["1", "4"] {
+            let archive = dir.join(format!("input-{threads}.{ext}"));
+            let output = dir.join(format!("output-{threads}-{ext}"));
+            round_trip(&["--threads", threads], &dir.join("input"), &archive, &output);
+            assert_eq!(fs::read(output.join(format!("input-{threads}"))).unwrap(), data);
+        }
+        assert_eq!(
+            fs::read(dir.join(format!("input-1.{ext}"))).unwrap(),
+            fs::read(dir.join(format!("input-4.{ext}"))).unwrap(),
+        );
+    }
+}
+
+// BGZF files, made of independent blocks, are decompressed in parallel
+#[test]
+fn parallel_gzip_decompression() {
+    use flate2::{write::DeflateEncoder, Compression, Crc};
+
+    // Writes `data` as BGZF, gzip members of at most 0xff00 bytes with their sizes in the header, like bgzip
+    fn bgzf(data: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+        // An empty block ends the file
+        for block in data.chunks(0xff00).chain(once(&[][..])) {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(block).unwrap();
+            let deflated = encoder.finish().unwrap();
+            let mut crc = Crc::new();
+            crc.update(block);
+
+            let block_size = (18 + deflated.len() + 8 - 1) as u16;
+            output.extend([0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0]);
+            output.extend(block_size.to_le_bytes());
+            output.extend(deflated);
+            output.extend(crc.sum().to_le_bytes());
+            output.extend((block.len() as u32).to_le_bytes());
+        }
+        output
+    }
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let mut rng = SmallRng::from_entropy();
+    create_files(
+        dir.join("input"),
+        (0..20).map(|i| {
+            let mut data = vec![0; 1 << 16];
+            rng.fill(&mut data[..1 << 15]);
+            (i.to_string(), data)
+        }),
+    );
+    ouch!("c", dir.join("input"), dir.join("input.tar"));
+    fs::write(
+        dir.join("input.tar.gz"),
+        bgzf(&fs::read(dir.join("input.tar")).unwrap()),
+    )
+    .unwrap();
+
+    for threads in ["1", "4"] {
+        let output = dir.join(format!("output-{threads}"));
+        cargo_bin()
+            .args(["--threads", threads, "d"])
+            .arg(dir.join("input.tar.gz"))
+            .arg("--dir")
+            .arg(&output)
+            .assert()
+            .success();
+        assert_same_directory(dir.join("input"), output.join("input"), false);
+
+        let listed = cargo_bin()
+            .args(["--threads", threads, "l"])
+            .arg(dir.join("input.tar.gz"))
+            .assert()
+            .success();
+        assert!(String::from_utf8_lossy(&listed.get_output().stdout).contains("input/19"));
+    }
+
+    // the sizes written at the end of the blocks aren't trusted when decompression limits are set
+    let output = dir.join("output-limited");
+    cargo_bin()
+        .args(["--threads", "4", "d"])
+        .arg(dir.join("input.tar.gz"))
+        .arg("--dir")
+        .arg(&output)
+        .args(["--max-ratio", "100"])
+        .assert()
+        .success();
+    assert_same_directory(dir.join("input"), output.join("input"), false);
+}
+
+// zip entries are extracted in parallel, directories get their modification times once their files are written
+#[test]
+fn parallel_zip_extraction() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let mut rng = SmallRng::from_entropy();
+    create_files(
+        dir.join("input"),
+        ["a", "b/c"]
+            .into_iter()
+            .flat_map(|subdir| (0..50).map(move |i| format!("{subdir}/{i}")))
+            .map(|path| {
+                let mut content = vec![];
+                write_random_content(&mut content, &mut rng);
+                (path, content)
+            }),
+    );
+    let mtime = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(dir.join("input/a"), mtime).unwrap();
+
+    for archive in ["archive.zip", "archive.zip.zst"] {
+        let output = dir.join("output");
+        round_trip(&["--threads", "4"], &dir.join("input"), &dir.join(archive), &output);
+
+        assert_same_directory(dir.join("input"), output.join("input"), true);
+        // Set once the files inside were written
+        let metadata = fs::metadata(output.join("input/a")).unwrap();
+        assert_eq!(filetime::FileTime::from_last_modification_time(&metadata), mtime);
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    // of the entries with the same path, the last one is extracted, not several at the same time
+    let archive = dir.join("duplicates.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    for i in 0..20u8 {
+        writer.start_file("file", Default::default()).unwrap();
+        writer.write_all(&vec![i; (20 - i as usize) << 12]).unwrap();
+    }
+    writer.finish().unwrap();
+    let output = dir.join("duplicates");
+    cargo_bin()
+        .args(["--threads", "4", "d"])
+        .arg(&archive)
+        .arg("--dir")
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(fs::read(output.join("file")).unwrap(), vec![19; 1 << 12]);
+}
+
+// --opt passes format specific settings, which are rejected when they don't apply
+#[test]
+fn format_options() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    // Repeated further apart than the default zstd window
+    let mut data = vec![0; 1 << 20];
+    SmallRng::from_entropy().fill(&mut data[..]);
+    let data = data.repeat(3);
+    create_files(dir, [("input", &data)]);
+
+    // The window has to be allowed when decompressing too
+    let archive = dir.join("input.zst");
+    round_trip(
+        &["--opt", "zstd.long=30", "--opt", "zstd.level=-5"],
+        &dir.join("input"),
+        &archive,
+        &dir.join("output"),
+    );
+    assert!(fs::metadata(&archive).unwrap().len() < 2 << 20);
+    assert_eq!(fs::read(dir.join("output/input")).unwrap(), data);
+    cargo_bin()
+        .args(["--yes", "d"])
+        .arg(&archive)
+        .arg("--dir")
+        .arg(dir.join("rejected"))
+        .assert()
+        .failure();
+
+    for (option, output) in [
+        ("zstd.long=27", "rejected.xz"),
+        ("zstd.long=40", "rejected.zst"),
+        ("xz.unknown=1", "rejected.xz"),
+        ("lz4.block-size=3M", "rejected.lz4"),
+    ] {
+        cargo_bin()
+            .args(["--yes", "c", "--opt", option])
+            .arg(dir.join("input"))
+            .arg(dir.join(output))
+            .assert()
+            .failure();
+        assert!(!dir.join(output).exists());
+    }
+}
+
+// zip archives wrapped in other formats or written to stdout don't go through memory
+#[test]
+fn wrapped_zip_archives() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    create_files(dir, [("input/dir/file", "content")]);
+
+    // Built in temporary files and read from streams or temporary files instead of memory, so there is nothing
+    // to warn about or confirm
+    let archive = dir.join("archive.zip.zst");
+    let stderr = round_trip(&[], &dir.join("input"), &archive, &dir.join("output"));
+    assert!(!stderr.contains("in-memory"), "{stderr}");
+    assert_eq!(fs::read(dir.join("output/input/dir/file")).unwrap(), b"content");
+
+    for args in [["l"], ["t"]] {
+        let assert = cargo_bin().args(args).arg(&archive).assert().success();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+        assert!(!stderr.contains("in-memory"), "{stderr}");
+    }
+
+    let output = cargo_bin()
+        .current_dir(dir)
+        .args(["cat"])
+        .arg(&archive)
+        .arg("input/dir/file")
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"content");
+
+    let output = cargo_bin()
+        .args(["c", "--format", "zip"])
+        .arg(dir.join("input"))
+        .arg("-")
+        .output()
+        .unwrap();
+    let mut zip_archive = zip::ZipArchive::new(std::io::Cursor::new(output.stdout)).unwrap();
+    let mut content = String::new();
+    zip_archive
+        .by_name("input/dir/file")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "content");
+}
+
+// zip archives read through other formats are extracted from their local headers, and from a temporary file
+// from the first entry whose sizes are in a data descriptor
+#[cfg(unix)]
+#[test]
+fn streamed_zip_archives() {
+    use std::os::unix::fs::PermissionsExt;
+
+    use flate2::{write::GzEncoder, Compression, Crc};
+
+    // Writes a zip archive of stored entries: name, content, unix mode and whether a data descriptor is used
+    fn zip_archive(entries: &[(&str, &str, u32, bool)]) -> Vec<u8> {
+        let (mut output, mut central_directory) = (vec![], vec![]);
+        for &(name, content, mode, data_descriptor) in entries {
+            let mut crc = Crc::new();
+            crc.update(content.as_bytes());
+            let mut sizes = crc.sum().to_le_bytes().to_vec();
+            sizes.extend((content.len() as u32).to_le_bytes().repeat(2));
+            let flags: u16 = if data_descriptor { 1 << 3 } else { 0 };
+
+            let offset = output.len() as u32;
+            output.extend(0x04034b50u32.to_le_bytes());
+            output.extend([20, 0]);
+            output.extend(flags.to_le_bytes());
+            // Stored, without modification time
+            output.extend([0; 6]);
+            output.extend(if data_descriptor { vec![0; 12] } else { sizes.clone() });
+            output.extend((name.len() as u16).to_le_bytes());
+            output.extend([0; 2]);
+            output.extend(name.as_bytes());
+            output.extend(content.as_bytes());
+            if data_descriptor {
+                output.extend(0x08074b50u32.to_le_bytes());
+                output.extend(&sizes);
+            }
+
+            central_directory.extend(0x02014b50u32.to_le_bytes());
+            // Made by unix
+            central_directory.extend([20, 3, 20, 0]);
+            central_directory.extend(flags.to_le_bytes());
+            central_directory.extend([0; 6]);
+            central_directory.extend(&sizes);
+            central_directory.extend((name.len() as u16).to_le_bytes());
+            central_directory.extend([0; 8]);
+            central_directory.extend((mode << 16).to_le_bytes());
+            central_directory.extend(offset.to_le_bytes());
+            central_directory.extend(name.as_bytes());
+        }
+
+        let offset = output.len() as u32;
+        output.extend(&central_directory);
+        output.extend(0x06054b50u32.to_le_bytes());
+        output.extend([0; 4]);
+        output.extend((entries.len() as u16).to_le_bytes().repeat(2));
+        output.extend((central_directory.len() as u32).to_le_bytes());
+        output.extend(offset.to_le_bytes());
+        output.extend([0; 2]);
+        output
+    }
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+
+    for spilled in [false, true] {
+        let archive = &dir.join(format!("spilled-{spilled}.zip.gz"));
+        let mut encoder = GzEncoder::new(fs::File::create(archive).unwrap(), Compression::default());
+        encoder
+            .write_all(&zip_archive(&[
+                ("dir/", "", 0o40700, false),
+                ("dir/streamed", "streamed", 0o100600, false),
+                ("dir/link", "streamed", 0o120777, false),
+                ("dir/evil", "/etc", 0o120777, false),
+                ("dir/spilled", "spilled", 0o100640, spilled),
+                ("dir/last", "last", 0o100755, false),
+            ]))
+            .unwrap();
+        encoder.finish().unwrap();
+
+        ouch!("-A", "t", archive);
+
+        // what's left of the archive after a data descriptor is stored next to it, not in the temporary directory
+        let output = cargo_bin()
+            .args(["-A", "--yes", "list", "--output", "json"])
+            .arg(archive)
+            .env("TMPDIR", dir.join("missing"))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let archives: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let entries = archives[0]["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 6);
+        for (path, kind, mode, link_target) in [
+            ("dir/streamed", "file", 0o600, None),
+            ("dir/link", "symlink", 0o777, Some("streamed")),
+            ("dir/spilled", "file", 0o640, None),
+            ("dir/last", "file", 0o755, None),
+        ] {
+            let entry = entries.iter().find(|entry| entry["path"] == path).unwrap();
+            assert_eq!(entry["type"], kind);
+            assert_eq!(entry["mode"], mode);
+            assert_eq!(entry["link_target"], serde_json::json!(link_target));
+        }
+
+        let out = &dir.join(format!("out-{spilled}"));
+        ouch!("-A", "d", archive, "-d", out);
+
+        let out = &out.join("dir");
+        for (name, content, mode) in [
+            ("streamed", "streamed", 0o600),
+            ("spilled", "spilled", 0o640),
+            ("last", "last", 0o755),
+        ] {
+            assert_eq!(fs::read_to_string(out.join(name)).unwrap(), content);
+            assert_eq!(fs::metadata(out.join(name)).unwrap().permissions().mode() & 0o777, mode);
+        }
+        assert_eq!(fs::metadata(out).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(fs::read_link(out.join("link")).unwrap(), PathBuf::from("streamed"));
+        assert!(fs::symlink_metadata(out.join("evil")).is_err());
+    }
+
+    // what is spilled after an entry with a data descriptor is limited too, not only the extracted entries
+    let archive = &dir.join("bomb.zip.gz");
+    let mut encoder = GzEncoder::new(fs::File::create(archive).unwrap(), Compression::default());
+    encoder
+        .write_all(&zip_archive(&[("spilled", "spilled", 0o100644, true)]))
+        .unwrap();
+    encoder.write_all(&vec![0; 16 << 20]).unwrap();
+    encoder.finish().unwrap();
+    let output = cargo_bin()
+        .args(["-A", "--yes", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(dir.join("bomb"))
+        .args(["--max-size", "1M"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("would be decompressed"));
+}
+
+// the progress line is only drawn when stderr is a terminal
+#[test]
+fn no_progress_outside_of_terminals() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    create_files(dir, [("input/file", vec![7; 1 << 20])]);
+
+    let stderr = round_trip(
+        &[],
+        &dir.join("input"),
+        &dir.join("archive.tar.zst"),
+        &dir.join("output"),
+    );
+
+    // stderr isn't a terminal here, so the progress line, which is redrawn with escape codes, is left out
+    assert!(!stderr.contains("\x1b[2K"), "{stderr}");
+    assert!(!stderr.contains("%)"), "{stderr}");
+}
+
+// --exclude, --include and --exclude-from leave files out of the archive
+#[test]
+fn exclude_and_include_globs() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(
+        before,
+        [
+            "src/main.rs",
+            "src/gen/parser.rs",
+            "target/debug/main",
+            "logs/run.log",
+            "README.md",
+            "notes.txt",
+        ]
+        .map(|file| (file, file)),
+    );
+    let exclude_file = &dir.join("exclude");
+    fs::write(exclude_file, "# not needed\n\nnotes.txt\n").unwrap();
+
+    for ext in ["tar", "zip"] {
+        let archive = &dir.join(format!("excluded.{ext}"));
+        ouch!(
+            "-A",
+            "c",
+            before,
+            archive,
+            "--exclude",
+            "target/",
+            "--exclude",
+            "*.log",
+            // anchored to the compressed directory, since it has a slash
+            "--exclude",
+            "src/gen",
+            "--exclude-from",
+            exclude_file
+        );
+        let out = &dir.join(format!("excluded-{ext}"));
+        ouch!("-A", "d", archive, "-d", out);
+        let after = &out.join("before");
+        assert!(after.join("src/main.rs").exists());
+        assert!(after.join("README.md").exists());
+        assert!(!after.join("src/gen").exists());
+        assert!(!after.join("target").exists());
+        assert!(!after.join("logs/run.log").exists());
+        assert!(!after.join("notes.txt").exists());
+
+        let archive = &dir.join(format!("included.{ext}"));
+        ouch!("-A", "c", before, archive, "--include", "*.rs");
+        let out = &dir.join(format!("included-{ext}"));
+        ouch!("-A", "d", archive, "-d", out);
+        let after = &out.join("before");
+        assert!(after.join("src/main.rs").exists());
+        assert!(!after.join("README.md").exists());
+        assert!(!after.join("target/debug/main").exists());
+    }
+}
+
+// entries can be added, updated and removed from existing tar and zip archives
+#[test]
+fn modify_archives() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(
+        before,
+        [("kept", "kept"), ("changed", "old"), ("sub/removed", "removed")],
+    );
+    create_files(dir, [("added", "added")]);
+    let added = &dir.join("added");
+
+    for ext in ["tar", "tar.zst", "zip"] {
+        let archive = &dir.join(format!("archive.{ext}"));
+        fs::write(before.join("changed"), "old").unwrap();
+        ouch!("-A", "c", before, archive);
+
+        ouch!("-A", "add", archive, added);
+
+        fs::write(before.join("changed"), "new").unwrap();
+        let later = filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() + 10, 0);
+        filetime::set_file_mtime(before.join("changed"), later).unwrap();
+        ouch!("-A", "update", archive, before);
+
+        ouch!("-A", "delete", archive, "before/sub");
+
+        let out = &dir.join(format!("out-{ext}"));
+        ouch!("-A", "d", archive, "-d", out);
+        // the archive has several top-level entries now, so they're extracted into a directory named after it
+        let out = &out.join("archive");
+        assert_eq!(fs::read_to_string(out.join("added")).unwrap(), "added");
+        assert_eq!(fs::read_to_string(out.join("before/kept")).unwrap(), "kept");
+        assert_eq!(fs::read_to_string(out.join("before/changed")).unwrap(), "new");
+        assert!(!out.join("before/sub").exists());
+    }
+
+    // single file formats have no entries to modify
+    let compressed = &dir.join("added.gz");
+    fs::write(compressed, "").unwrap();
+    cargo_bin()
+        .args(["-A", "--yes", "add"])
+        .arg(compressed)
+        .arg(added)
+        .assert()
+        .failure();
+}
+
+// hard links keep the data of the tar entry they point to when it's removed or replaced
+#[cfg(unix)]
+#[test]
+fn modify_hard_linked_archives() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("a", "old")]);
+    fs::hard_link(before.join("a"), before.join("b")).unwrap();
+    fs::hard_link(before.join("a"), before.join("c")).unwrap();
+    let deleted = &dir.join("deleted.tar");
+    let updated = &dir.join("updated.tar.gz");
+    // sorted, so that the data is stored under `a`, with modification times that aren't clamped
+    for archive in [deleted, updated] {
+        cargo_bin()
+            .args(["-A", "--yes", "c", "--reproducible"])
+            .arg(before)
+            .arg(archive)
+            .env("SOURCE_DATE_EPOCH", "4000000000")
+            .assert()
+            .success();
+    }
+
+    ouch!("-A", "delete", deleted, "before/a");
+    let out = &dir.join("out-deleted");
+    ouch!("-A", "d", deleted, "-d", out);
+    let after = &out.join("before");
+    assert!(!after.join("a").exists());
+    assert_eq!(fs::read_to_string(after.join("b")).unwrap(), "old");
+    assert_eq!(fs::read_to_string(after.join("c")).unwrap(), "old");
+    assert_eq!(
+        fs::metadata(after.join("b")).unwrap().ino(),
+        fs::metadata(after.join("c")).unwrap().ino()
+    );
+
+    // a new file replaces `a` at the end of the archive, the links keep the old one
+    fs::remove_file(before.join("a")).unwrap();
+    fs::write(before.join("a"), "new").unwrap();
+    let later = filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() + 10, 0);
+    filetime::set_file_mtime(before.join("a"), later).unwrap();
+    ouch!("-A", "update", updated, before);
+    let out = &dir.join("out-updated");
+    ouch!("-A", "d", updated, "-d", out);
+    let after = &out.join("before");
+    assert_eq!(fs::read_to_string(after.join("a")).unwrap(), "new");
+    assert_eq!(fs::read_to_string(after.join("b")).unwrap(), "old");
+    assert_eq!(fs::read_to_string(after.join("c")).unwrap(), "old");
+}
+
+// only the archive entries matching the given paths or globs are extracted
+#[proptest(cases = 32)]
+fn extract_selected_entries(ext: DirectoryExtension) {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(
+        before,
+        ["docs/a.md", "docs/guide/b.md", "docs/c.txt", "bin/tool", "README.md"].map(|file| (file, file)),
+    );
+    let archive = &dir.join(format!("archive.{ext}"));
+    ouch!("-A", "c", before, archive);
+
+    let after = &dir.join("after");
+    ouch!(
+        "-A",
+        "d",
+        archive,
+        "-d",
+        after,
+        "-e",
+        "before/docs/**/*.md",
+        "--entry",
+        "before/bin/tool"
+    );
+    let after = &after.join("before");
+    assert!(after.join("docs/a.md").exists());
+    assert!(after.join("docs/guide/b.md").exists());
+    assert!(after.join("bin/tool").exists());
+    assert!(!after.join("docs/c.txt").exists());
+    assert!(!after.join("README.md").exists());
+
+    // patterns that select nothing are an error
+    let empty = &dir.join("empty");
+    cargo_bin()
+        .args(["-A", "d"])
+        .arg(archive)
+        .arg("-d")
+        .arg(empty)
+        .args(["-e", "missing/**"])
+        .assert()
+        .failure();
+    assert_eq!(fs::read_dir(empty).unwrap().count(), 0);
+}
+
+// links have no content of their own, so printing one is an error naming its target, instead of empty output
+#[cfg(unix)]
+#[test]
+fn cat_link_entry() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("config/app.toml", "name = \"ouch\"\n")]);
+    std::os::unix::fs::symlink("config/app.toml", before.join("symlink")).unwrap();
+    fs::hard_link(before.join("config/app.toml"), before.join("hardlink")).unwrap();
+
+    for format in ["tar.gz", "zip"] {
+        let archive = &dir.join(format!("archive.{format}"));
+        // Sorted, so the hardlink comes after the file it links to
+        ouch!("-A", "--yes", "c", before, archive, "--reproducible");
+
+        let output = cargo_bin()
+            .args(["-A", "cat"])
+            .arg(archive)
+            .arg("before/symlink")
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{format}");
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("is a link to"));
+    }
+
+    // hard links are regular files, whose data is stored under the entry they link to
+    let archive = &dir.join("archive.tar.gz");
+    cargo_bin()
+        .args(["-A", "cat"])
+        .arg(archive)
+        .arg("before/hardlink")
+        .assert()
+        .success()
+        .stdout("name = \"ouch\"\n");
+
+    // which can't be read again from stdin
+    cargo_bin()
+        .args(["-A", "cat", "-", "before/hardlink", "--format", "tar.gz"])
+        .write_stdin(fs::read(archive).unwrap())
+        .assert()
+        .failure()
+        .stdout("");
+}
+
+// print one archive entry, or a whole decompressed file, to stdout
+#[proptest(cases = 16)]
+fn cat_entry(ext: DirectoryExtension) {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("config/app.toml", "name = \"ouch\"\n"), ("other", "other")]);
+    let archive = &dir.join(format!("archive.{ext}"));
+    ouch!("-A", "c", before, archive);
+
+    cargo_bin()
+        .args(["-A", "--yes", "cat"])
+        .arg(archive)
+        .arg("before/config/app.toml")
+        .assert()
+        .success()
+        .stdout("name = \"ouch\"\n");
+
+    // entries that don't exist are reported with the closest paths
+    let output = cargo_bin()
+        .args(["-A", "--yes", "cat"])
+        .arg(archive)
+        .arg("before/config/app.tml")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("before/config/app.toml"));
+
+    // single file formats are printed as a whole
+    let file = &dir.join("file.txt.gz");
+    ouch!("-A", "c", before.join("other"), file);
+    cargo_bin()
+        .args(["-A", "--yes", "cat"])
+        .arg(file)
+        .assert()
+        .success()
+        .stdout("other");
+
+    // formats detected from the contents are used without asking, questions would be mixed with the output
+    let renamed = &dir.join("file");
+    fs::copy(file, renamed).unwrap();
+    cargo_bin()
+        .args(["-A", "cat"])
+        .arg(renamed)
+        .assert()
+        .success()
+        .stdout("other");
+}
+
+// compress to stdout and decompress from stdin, with `-` in place of the file paths
+#[proptest(cases = 16)]
+fn stdin_and_stdout(
+    ext: DirectoryExtension,
+    #[any(size_range(0..4).lift())] exts: Vec<FileExtension>,
+    #[strategy(0u8..3)] depth: u8,
+) {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    let after = &dir.join("after");
+    create_random_files(before_dir, depth, &mut SmallRng::from_entropy());
+    let format = merge_extensions(&ext, exts);
+
+    let output = cargo_bin()
+        .args(["-A", "--yes", "c"])
+        .arg(before_dir)
+        .args(["-", "--format", &format])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    cargo_bin()
+        .args(["-A", "--yes", "d", "-", "--format", &format, "-d"])
+        .arg(after)
+        .write_stdin(output.stdout)
+        .assert()
+        .success();
+    assert_same_directory(
+        before,
+        after,
+        !matches!(ext, DirectoryExtension::Zip | DirectoryExtension::SevenZ),
+    );
+}
+
+// the format of stdin is detected from its first bytes when `--format` isn't given
+#[test]
+fn stdin_format_detection() {
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    create_files(before, [("file", "content")]);
+    let archive = &dir.join("archive.zip");
+    ouch!("-A", "c", before, archive);
+
+    let after = &dir.join("after");
+    cargo_bin()
+        .args(["-A", "d", "-", "-d"])
+        .arg(after)
+        .write_stdin(fs::read(archive).unwrap())
+        .assert()
+        .success();
+    assert_eq!(fs::read(after.join("before/file")).unwrap(), b"content");
+
+    // compressing to stdout needs the formats
+    cargo_bin()
+        .args(["-A", "c"])
+        .arg(before)
+        .arg("-")
+        .assert()
+        .failure()
+        .stdout("");
+}
+
+// convert archives between formats and check that their content is kept
+#[proptest(cases = 32)]
+fn convert_archives(
+    from: DirectoryExtension,
+    #[strategy(proptest::sample::select(&["tar", "tar.zst", "tgz", "zip", "zip.gz"][..]))] to: &'static str,
+    #[strategy(0u8..3)] depth: u8,
+) {
+    // 7z archives can't be converted to other archive formats
+    if matches!(from, DirectoryExtension::SevenZ) {
+        return Ok(());
+    }
+
+    let dir = tempdir().unwrap();
+    let dir = dir.path();
+    let before = &dir.join("before");
+    let before_dir = &before.join("dir");
+    fs::create_dir_all(before_dir).unwrap();
+    create_random_files(before_dir, depth, &mut SmallRng::from_entropy());
+    let archive = &dir.join(format!("archive.{from}"));
+    let converted = &dir.join(format!("converted.{to}"));
+    let after = &dir.join("after");
+    ouch!("-A", "c", before_dir, archive);
+    ouch!("-A", "convert", archive, converted);
+    ouch!("-A", "d", converted, "-d", after);
+    assert_same_directory(before, after, false);
 }